@@ -0,0 +1,28 @@
+//! Compares counting tokens with `count_tokens` against lexing them all
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use oxy_python::parser::{count_tokens, lex};
+
+/// A few kilobytes of Python, built from the lexer corpus
+fn source() -> String {
+    let corpus = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/corpus");
+    let mut source = String::new();
+    for entry in std::fs::read_dir(corpus).unwrap() {
+        source.push_str(&std::fs::read_to_string(entry.unwrap().path()).unwrap());
+        source.push('\n');
+    }
+    source
+}
+
+fn bench_count_tokens(c: &mut Criterion) {
+    let source = source();
+    c.bench_function("lex len", |b| {
+        b.iter(|| lex(black_box(&source)).map(|t| t.len()))
+    });
+    c.bench_function("count_tokens", |b| {
+        b.iter(|| count_tokens(black_box(&source)))
+    });
+}
+
+criterion_group!(benches, bench_count_tokens);
+criterion_main!(benches);
@@ -0,0 +1,20 @@
+//! Measures lexing source made mostly of names and keywords
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use oxy_python::parser::lex;
+
+/// Lines of names and keywords, as a highlighter over a large file would see
+fn source() -> String {
+    let line = "if value and not other_value or flag in items: return result is None\n";
+    line.repeat(2000)
+}
+
+fn bench_keywords(c: &mut Criterion) {
+    let source = source();
+    c.bench_function("lex names and keywords", |b| {
+        b.iter(|| lex(black_box(&source)))
+    });
+}
+
+criterion_group!(benches, bench_keywords);
+criterion_main!(benches);
@@ -1,24 +1,189 @@
 
+extern crate directories;
+extern crate oxy_python;
 extern crate rustyline;
 
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use directories::ProjectDirs;
+use oxy_python::parser::{
+    current_indent, ends_with_continuation, format_source, join_continued_lines, lex,
+    pending_block, render_tokens, ColorMode,
+};
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
 
-const HISTORY_FILE: &'static str = "oxy-py-history.txt";
+const HISTORY_FILE: &str = "history.txt";
+/// How far the continuation prompt indents a new block
+const BLOCK_INDENT: u64 = 4;
+
+/// Where the history is kept, in the per-user data directory when there is one
+fn history_path() -> PathBuf {
+    let dirs = ProjectDirs::from("", "", "oxy-python");
+    resolve_history_path(dirs.as_ref().map(ProjectDirs::data_dir))
+}
+
+/// Put the history file in `data_dir`, creating it if needed.
+/// Falls back to the current directory if there is no data directory or it cannot be made.
+fn resolve_history_path(data_dir: Option<&Path>) -> PathBuf {
+    match data_dir {
+        Some(dir) if fs::create_dir_all(dir).is_ok() => dir.join(HISTORY_FILE),
+        _ => PathBuf::from(HISTORY_FILE),
+    }
+}
+
+const FMT_USAGE: &str = "usage: oxy-py fmt [--check | --stdout] <file>";
+
+/// Format a file in place, or with `--check` only report whether it needs it.
+/// Returns the exit code: 1 when `--check` finds changes, 2 on any error.
+fn run_fmt(args: &[String]) -> i32 {
+    let (mode, path) = match args {
+        [path] => ("", path),
+        [mode, path] if mode == "--check" || mode == "--stdout" => (mode.as_str(), path),
+        _ => {
+            eprintln!("{}", FMT_USAGE);
+            return 2;
+        }
+    };
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("Could not read {}: {}", path, err);
+            return 2;
+        }
+    };
+    let formatted = match format_source(&source) {
+        Ok(formatted) => formatted,
+        Err(err) => {
+            eprintln!("Could not format {}: {}", path, err);
+            return 2;
+        }
+    };
+
+    match mode {
+        "--check" if formatted != source => {
+            println!("{} would be reformatted", path);
+            1
+        }
+        "--check" => 0,
+        "--stdout" => {
+            print!("{}", formatted);
+            0
+        }
+        _ => {
+            if formatted != source {
+                if let Err(err) = fs::write(path, &formatted) {
+                    eprintln!("Could not write {}: {}", path, err);
+                    return 2;
+                }
+            }
+            0
+        }
+    }
+}
+
+const TOKENS_USAGE: &str = "usage: oxy-py tokens [--color auto|always|never] <file>";
+
+/// Print the tokens of a file one per line, colored by category when asked
+/// or when stdout is a terminal. Returns the exit code, 2 on any error.
+fn run_tokens(args: &[String]) -> i32 {
+    let (mode, path) = match args {
+        [path] => ("auto", path),
+        [flag, mode, path] if flag == "--color" => (mode.as_str(), path),
+        _ => {
+            eprintln!("{}", TOKENS_USAGE);
+            return 2;
+        }
+    };
+    let color: ColorMode = match mode.parse() {
+        Ok(color) => color,
+        Err(err) => {
+            eprintln!("Bad --color: {}", err);
+            eprintln!("{}", TOKENS_USAGE);
+            return 2;
+        }
+    };
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("Could not read {}: {}", path, err);
+            return 2;
+        }
+    };
+    match lex(&source) {
+        Ok(tokens) => {
+            let color = color.use_color(io::stdout().is_terminal());
+            print!("{}", render_tokens(&tokens, color));
+            0
+        }
+        Err(err) => {
+            eprintln!("Could not lex {}: {}", path, err);
+            2
+        }
+    }
+}
 
 fn main() {
+    let args: Vec<String> = env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("fmt") => process::exit(run_fmt(&args[2..])),
+        Some("tokens") => process::exit(run_tokens(&args[2..])),
+        _ => {}
+    }
+
     // `()` can be used when no completer is required
     let mut rl = Editor::<()>::new();
-    let _ = rl.load_history(HISTORY_FILE);
-    println!("Oxy Python {}", "alpha");
+    let history = history_path();
+    // There is no history on the first run
+    let _ = rl.load_history(&history);
+    println!("Oxy Python alpha");
     println!();
 
+    // The lines of a block that is still being typed
+    let mut input = String::new();
+
     loop {
-        let readline = rl.readline(">>> ");
+        let readline = if input.is_empty() {
+            rl.readline(">>> ")
+        } else if ends_with_continuation(&input) {
+            // The last line asked to be continued, carry on where it left off
+            rl.readline("... ")
+        } else {
+            // Start the next line at the indentation the block needs
+            let mut indent = current_indent(&input);
+            if pending_block(&input) {
+                indent += BLOCK_INDENT;
+            }
+            let indent = " ".repeat(indent as usize);
+            rl.readline_with_initial("... ", (&indent, ""))
+        };
         match readline {
             Ok(line) => {
+                if line.trim().is_empty() {
+                    // Empty and whitespace only input does nothing, or ends an open block
+                    if !input.is_empty() && !ends_with_continuation(&input) {
+                        println!("Line: {}", join_continued_lines(&input).trim_end());
+                        input.clear();
+                    }
+                    continue;
+                }
                 rl.add_history_entry(line.as_str());
-                println!("Line: {}", line);
+                // Piped input keeps its line endings
+                input.push_str(line.trim_end_matches(['\r', '\n']));
+                input.push('\n');
+
+                // Keep reading while a line is continued or a block is open
+                if ends_with_continuation(&input) {
+                    continue;
+                }
+                if !pending_block(&input) && current_indent(&input) == 0 {
+                    println!("Line: {}", join_continued_lines(&input).trim_end());
+                    input.clear();
+                }
             },
             Err(ReadlineError::Eof) => {
                 println!("CTRL-D");
@@ -30,5 +195,28 @@ fn main() {
             }
         }
     }
-    rl.save_history(HISTORY_FILE).unwrap();
+    if let Err(err) = rl.save_history(&history) {
+        println!("Could not save history to {}: {}", history.display(), err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_history_path() {
+        let dir = std::env::temp_dir().join(format!("oxy-python-history-{}", std::process::id()));
+        let nested = dir.join("data");
+        assert_eq!(resolve_history_path(Some(&nested)), nested.join(HISTORY_FILE));
+        assert!(nested.is_dir());
+
+        // A data directory that cannot be created falls back to the current directory
+        let file = dir.join("file");
+        fs::write(&file, "").unwrap();
+        assert_eq!(resolve_history_path(Some(&file.join("data"))), PathBuf::from(HISTORY_FILE));
+        assert_eq!(resolve_history_path(None), PathBuf::from(HISTORY_FILE));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
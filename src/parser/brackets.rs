@@ -0,0 +1,168 @@
+//! The brackets module checks that brackets are balanced
+//! and nested, without parsing the tokens
+
+use super::lexer::{Location, Token, TokenType};
+use std::fmt;
+
+/// Why brackets do not balance, with the position of the offending bracket
+#[derive(Debug, Clone, PartialEq)]
+pub enum BracketError {
+    /// A closing bracket that does not match the innermost open bracket
+    Mismatched {
+        expected: TokenType,
+        found: TokenType,
+        at: Location,
+    },
+    /// A closing bracket with no open bracket before it
+    Unopened { found: TokenType, at: Location },
+    /// A bracket that is still open at the end of the tokens
+    Unclosed { open: TokenType, at: Location },
+}
+
+impl fmt::Display for BracketError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BracketError::Mismatched {
+                expected,
+                found,
+                at,
+            } => write!(
+                f,
+                "expected {} but found {} at {}",
+                expected.kind_name(),
+                found.kind_name(),
+                at
+            ),
+            BracketError::Unopened { found, at } => {
+                write!(f, "{} at {} closes nothing", found.kind_name(), at)
+            }
+            BracketError::Unclosed { open, at } => {
+                write!(f, "{} at {} is never closed", open.kind_name(), at)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BracketError {}
+
+/// The closing bracket for an opening bracket
+fn closer(open: &TokenType) -> Option<TokenType> {
+    match open {
+        TokenType::LeftParen => Some(TokenType::RightParen),
+        TokenType::LeftBracket => Some(TokenType::RightBracket),
+        TokenType::LeftBrace => Some(TokenType::RightBrace),
+        _ => None,
+    }
+}
+
+/// Check that every `(`, `[` and `{` is closed by the matching bracket, in order.
+///
+/// The first problem found is reported. An opening bracket is only reported as
+/// unclosed once the whole stream has been checked, at the position of the
+/// innermost one still open.
+///
+/// ```
+/// extern crate oxy_python;
+/// use oxy_python::parser::{check_brackets, lex};
+/// assert!(check_brackets(&lex("f([1], {2: (3)})").unwrap()).is_ok());
+/// assert!(check_brackets(&lex("f([1)]").unwrap()).is_err());
+/// ```
+pub fn check_brackets(tokens: &[Token]) -> Result<(), BracketError> {
+    // The open brackets, innermost last
+    let mut open: Vec<&Token> = Vec::new();
+    for token in tokens {
+        let token_type = token.token_type();
+        match token_type {
+            TokenType::LeftParen | TokenType::LeftBracket | TokenType::LeftBrace => {
+                open.push(token)
+            }
+            TokenType::RightParen | TokenType::RightBracket | TokenType::RightBrace => {
+                let opener = match open.pop() {
+                    Some(opener) => opener,
+                    None => {
+                        return Err(BracketError::Unopened {
+                            found: token_type.clone(),
+                            at: *token.start(),
+                        })
+                    }
+                };
+                let expected = closer(opener.token_type()).unwrap();
+                if &expected != token_type {
+                    return Err(BracketError::Mismatched {
+                        expected,
+                        found: token_type.clone(),
+                        at: *token.start(),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    match open.pop() {
+        Some(opener) => Err(BracketError::Unclosed {
+            open: opener.token_type().clone(),
+            at: *opener.start(),
+        }),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::lexer::lex;
+    use super::*;
+
+    fn check(source: &str) -> Result<(), BracketError> {
+        check_brackets(&lex(source).unwrap())
+    }
+
+    #[test]
+    fn test_balanced() {
+        assert_eq!(check(""), Ok(()));
+        assert_eq!(check("x = [(1, 2), {3: [4]}]\n"), Ok(()));
+        assert_eq!(check("f(\n    a,\n    b,\n)\n"), Ok(()));
+    }
+
+    #[test]
+    fn test_mismatched() {
+        match check("([)]") {
+            Err(BracketError::Mismatched {
+                expected,
+                found,
+                at,
+            }) => {
+                assert_eq!(expected, TokenType::RightBracket);
+                assert_eq!(found, TokenType::RightParen);
+                assert_eq!((at.line(), at.column()), (1, 3));
+            }
+            other => panic!("Expected a mismatch. Received: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_unclosed() {
+        match check("((") {
+            Err(BracketError::Unclosed { open, at }) => {
+                assert_eq!(open, TokenType::LeftParen);
+                assert_eq!((at.line(), at.column()), (1, 2));
+            }
+            other => panic!("Expected an unclosed bracket. Received: {:?}", other),
+        }
+        let error = check("a = {\n").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "LeftBrace at line 1, column 5 is never closed"
+        );
+    }
+
+    #[test]
+    fn test_unopened() {
+        match check("a)\n") {
+            Err(BracketError::Unopened { found, at }) => {
+                assert_eq!(found, TokenType::RightParen);
+                assert_eq!((at.line(), at.column()), (1, 2));
+            }
+            other => panic!("Expected an unopened bracket. Received: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,144 @@
+//! The cache module holds a lexer that remembers
+//! the tokens of sources it has already lexed
+
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+
+use lru::LruCache;
+
+use super::lexer::{lex_with_options, LexError, LexerOptions, Token};
+
+/// A lexer that caches its results, for relexing the same sources repeatedly.
+///
+/// Results are keyed by the source itself and shared through an `Arc`, so each
+/// cached result keeps a copy of its source.
+/// Once the cache is full, the least recently used result is dropped.
+/// Sources that fail to lex are not cached.
+///
+/// ```
+/// extern crate oxy_python;
+/// use oxy_python::parser::CachedLexer;
+/// use std::num::NonZeroUsize;
+/// let mut lexer = CachedLexer::new(NonZeroUsize::new(16).unwrap());
+/// let first = lexer.lex("a + b").unwrap();
+/// let second = lexer.lex("a + b").unwrap();
+/// assert_eq!(lexer.hits(), 1);
+/// ```
+pub struct CachedLexer {
+    cache: LruCache<Box<str>, Arc<Vec<Token>>>,
+    options: LexerOptions,
+    hits: u64,
+}
+
+impl CachedLexer {
+    /// Create a cache holding up to `capacity` results, lexing with the default options
+    pub fn new(capacity: NonZeroUsize) -> CachedLexer {
+        CachedLexer::with_options(capacity, LexerOptions::default())
+    }
+
+    /// Create a cache holding up to `capacity` results, lexing with the given options
+    pub fn with_options(capacity: NonZeroUsize, options: LexerOptions) -> CachedLexer {
+        CachedLexer {
+            cache: LruCache::new(capacity),
+            options,
+            hits: 0,
+        }
+    }
+
+    /// Lex the source, reusing the cached tokens if it has been lexed before
+    pub fn lex(&mut self, source: &str) -> Result<Arc<Vec<Token>>, LexError> {
+        if let Some(tokens) = self.cache.get(source) {
+            self.hits += 1;
+            return Ok(Arc::clone(tokens));
+        }
+
+        let tokens = Arc::new(lex_with_options(source, &self.options)?);
+        self.cache.put(source.into(), Arc::clone(&tokens));
+        Ok(tokens)
+    }
+
+    /// The number of results the cache can hold
+    pub fn capacity(&self) -> NonZeroUsize {
+        self.cache.cap()
+    }
+
+    /// Change the number of results the cache can hold, dropping the oldest if it shrinks
+    pub fn resize(&mut self, capacity: NonZeroUsize) {
+        self.cache.resize(capacity);
+    }
+
+    /// The number of results in the cache
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// Asserts if the cache holds no results
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+
+    /// The number of times `lex` was answered from the cache
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// Drop every cached result
+    pub fn clear(&mut self) {
+        self.cache.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lexer(capacity: usize) -> CachedLexer {
+        CachedLexer::new(NonZeroUsize::new(capacity).unwrap())
+    }
+
+    #[test]
+    fn test_cache_hit() {
+        let mut lexer = lexer(4);
+        let first = lexer.lex("a + b").unwrap();
+        assert_eq!(lexer.hits(), 0);
+        let second = lexer.lex("a + b").unwrap();
+        assert_eq!(lexer.hits(), 1);
+        assert!(Arc::ptr_eq(&first, &second));
+        lexer.lex("a - b").unwrap();
+        assert_eq!(lexer.hits(), 1);
+        assert_eq!(lexer.len(), 2);
+    }
+
+    #[test]
+    fn test_least_recently_used_dropped() {
+        let mut lexer = lexer(2);
+        lexer.lex("a").unwrap();
+        lexer.lex("b").unwrap();
+        lexer.lex("a").unwrap();
+        lexer.lex("c").unwrap();
+        assert_eq!(lexer.len(), 2);
+        lexer.lex("b").unwrap();
+        assert_eq!(lexer.hits(), 1);
+    }
+
+    #[test]
+    fn test_errors_not_cached() {
+        let mut lexer = lexer(2);
+        assert!(lexer.lex("$").is_err());
+        assert!(lexer.is_empty());
+    }
+
+    #[test]
+    fn test_clear_and_resize() {
+        let mut lexer = lexer(4);
+        lexer.lex("a").unwrap();
+        lexer.lex("b").unwrap();
+        lexer.resize(NonZeroUsize::new(1).unwrap());
+        assert_eq!(lexer.len(), 1);
+        assert_eq!(lexer.capacity().get(), 1);
+        lexer.clear();
+        assert!(lexer.is_empty());
+        lexer.lex("b").unwrap();
+        assert_eq!(lexer.hits(), 0);
+    }
+}
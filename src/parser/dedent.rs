@@ -0,0 +1,78 @@
+//! The dedent module strips common indentation
+//! from source before it is lexed
+
+/// Remove the longest leading whitespace that every non-blank line shares.
+///
+/// This matches Python's `textwrap.dedent`. Only spaces and tabs count as
+/// whitespace, and a tab never matches spaces, so lines indented with a mix
+/// keep what they do not share. Lines made only of spaces and tabs become
+/// empty. The rewrite is purely textual: lines inside triple quoted strings
+/// lose the margin too, as they do with `textwrap.dedent`.
+///
+/// ```
+/// extern crate oxy_python;
+/// use oxy_python::parser::dedent;
+/// assert_eq!(dedent("    if a:\n        b\n"), "if a:\n    b\n");
+/// ```
+pub fn dedent(source: &str) -> String {
+    let is_indent = |c: char| c == ' ' || c == '\t';
+
+    // The shared margin, `None` until the first non-blank line
+    let mut margin: Option<&str> = None;
+    for line in source.split_inclusive('\n') {
+        let text = line.strip_suffix('\n').unwrap_or(line);
+        if text.chars().all(is_indent) {
+            continue;
+        }
+        let indent = &text[..text.len() - text.trim_start_matches(is_indent).len()];
+        margin = Some(match margin {
+            None => indent,
+            Some(margin) => {
+                let shared = margin
+                    .char_indices()
+                    .zip(indent.chars())
+                    .find(|((_, a), b)| a != b)
+                    .map_or(margin.len().min(indent.len()), |((index, _), _)| index);
+                &margin[..shared]
+            }
+        });
+    }
+    let margin = margin.unwrap_or("");
+
+    let mut result = String::with_capacity(source.len());
+    for line in source.split_inclusive('\n') {
+        let text = line.strip_suffix('\n').unwrap_or(line);
+        // Whitespace only lines are emptied, keeping the line break
+        if !text.chars().all(is_indent) {
+            result.push_str(text.strip_prefix(margin).unwrap_or(text));
+        }
+        if line.ends_with('\n') {
+            result.push('\n');
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dedent_like_textwrap() {
+        // Each expected value is what textwrap.dedent returns
+        assert_eq!(dedent("    hello\n      world\n"), "hello\n  world\n");
+        assert_eq!(dedent("  a\n\n  b"), "a\n\nb");
+        assert_eq!(dedent("  a\n    \n  b\n"), "a\n\nb\n");
+        assert_eq!(dedent("\ta\n    b\n"), "\ta\n    b\n");
+        assert_eq!(dedent("  \ta\n  b"), "\ta\nb");
+        assert_eq!(dedent("no indent\n  x\n"), "no indent\n  x\n");
+        assert_eq!(dedent(""), "");
+        assert_eq!(dedent("   \n\t\n"), "\n\n");
+    }
+
+    #[test]
+    fn test_dedent_is_textual() {
+        let source = "    s = '''\n    text\n    '''\n";
+        assert_eq!(dedent(source), "s = '''\ntext\n'''\n");
+    }
+}
@@ -0,0 +1,193 @@
+//! The diff module compares two token streams,
+//! for incremental updates in editors
+
+use super::lexer::Token;
+
+/// One step of an edit script turning an old token stream into a new one.
+///
+/// Indexes into the old stream refer to its positions before any edits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenEdit {
+    /// Remove the old token at `old`
+    Delete { old: usize },
+    /// Insert the new token at `new` before the old token at `old`
+    Insert { old: usize, new: usize },
+    /// Replace the old token at `old` with the new token at `new`
+    Replace { old: usize, new: usize },
+}
+
+/// Find a minimal edit script from `old` to `new` using Myers' diff.
+///
+/// Tokens match if their types and payloads are equal, so positions do not
+/// matter. A deletion next to an insertion at the same place becomes a `Replace`.
+///
+/// ```
+/// extern crate oxy_python;
+/// use oxy_python::parser::{lex, token_diff, TokenEdit};
+/// let edits = token_diff(&lex("a + b").unwrap(), &lex("a - b").unwrap());
+/// assert_eq!(edits, vec![TokenEdit::Replace { old: 1, new: 1 }]);
+/// ```
+pub fn token_diff(old: &[Token], new: &[Token]) -> Vec<TokenEdit> {
+    let n = old.len() as isize;
+    let m = new.len() as isize;
+    let max = n + m;
+    let same = |x: isize, y: isize| old[x as usize].token_type() == new[y as usize].token_type();
+    // The furthest x reached on each diagonal k = x - y, offset by max
+    let index = |k: isize| (k + max) as usize;
+    let mut v = vec![0isize; 2 * max as usize + 2];
+    // The diagonals -d..=d of v before each round d, for walking the path back.
+    // A round only reads the diagonals the last one wrote, so nothing else is kept.
+    let mut trace = Vec::new();
+
+    'search: for d in 0..=max {
+        trace.push(v[index(-d)..=index(d)].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) {
+                v[index(k + 1)]
+            } else {
+                v[index(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && same(x, y) {
+                x += 1;
+                y += 1;
+            }
+            v[index(k)] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    let mut edits = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, diagonals) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        if d == 0 {
+            break;
+        }
+        let v = |k: isize| diagonals[(k + d) as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && v(k - 1) < v(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = v(prev_k);
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+        }
+        if x == prev_x {
+            edits.push(TokenEdit::Insert {
+                old: x as usize,
+                new: prev_y as usize,
+            });
+        } else {
+            edits.push(TokenEdit::Delete {
+                old: prev_x as usize,
+            });
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    edits.reverse();
+
+    merge_replacements(edits)
+}
+
+/// Turn a deletion and an insertion at the same place into a replacement
+fn merge_replacements(edits: Vec<TokenEdit>) -> Vec<TokenEdit> {
+    let mut merged: Vec<TokenEdit> = Vec::with_capacity(edits.len());
+    for edit in edits {
+        let replacement = match (merged.last(), edit) {
+            (Some(TokenEdit::Delete { old }), TokenEdit::Insert { old: at, new })
+                if at == old + 1 =>
+            {
+                Some(TokenEdit::Replace { old: *old, new })
+            }
+            (Some(TokenEdit::Insert { old: at, new }), TokenEdit::Delete { old }) if *at == old => {
+                Some(TokenEdit::Replace { old, new: *new })
+            }
+            _ => None,
+        };
+        match replacement {
+            Some(replacement) => *merged.last_mut().unwrap() = replacement,
+            None => merged.push(edit),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::lex;
+
+    fn diff(old: &str, new: &str) -> Vec<TokenEdit> {
+        token_diff(&lex(old).unwrap(), &lex(new).unwrap())
+    }
+
+    #[test]
+    fn test_replace() {
+        assert_eq!(
+            diff("a + b", "a - b"),
+            vec![TokenEdit::Replace { old: 1, new: 1 }]
+        );
+    }
+
+    #[test]
+    fn test_insert_and_delete() {
+        assert!(diff("a + b", "a  +  b").is_empty());
+        assert_eq!(
+            diff("f(a)", "f(a, b)"),
+            vec![
+                TokenEdit::Insert { old: 3, new: 3 },
+                TokenEdit::Insert { old: 3, new: 4 },
+            ]
+        );
+        assert_eq!(
+            diff("f(a, b)", "f(b)"),
+            vec![TokenEdit::Delete { old: 2 }, TokenEdit::Delete { old: 3 }]
+        );
+        assert_eq!(diff("", "a"), vec![TokenEdit::Insert { old: 0, new: 0 }]);
+        assert_eq!(diff("a", ""), vec![TokenEdit::Delete { old: 0 }]);
+        assert!(diff("", "").is_empty());
+    }
+
+    #[test]
+    fn test_edits_rebuild_new() {
+        let old = lex("x = f(1, 2) + g\ny = 3").unwrap();
+        let new = lex("x = h(1) + g * 2\nz = 3").unwrap();
+        let edits = token_diff(&old, &new);
+
+        // Apply the edits by walking the old stream
+        let mut rebuilt = Vec::new();
+        let mut next = 0;
+        for edit in &edits {
+            let at = match *edit {
+                TokenEdit::Delete { old } | TokenEdit::Replace { old, .. } => old,
+                TokenEdit::Insert { old, .. } => old,
+            };
+            while next < at {
+                rebuilt.push(old[next].token_type().clone());
+                next += 1;
+            }
+            match *edit {
+                TokenEdit::Delete { .. } => next += 1,
+                TokenEdit::Replace { new: index, .. } => {
+                    rebuilt.push(new[index].token_type().clone());
+                    next += 1;
+                }
+                TokenEdit::Insert { new: index, .. } => {
+                    rebuilt.push(new[index].token_type().clone())
+                }
+            }
+        }
+        rebuilt.extend(old[next..].iter().map(|t| t.token_type().clone()));
+
+        let expected: Vec<_> = new.iter().map(|t| t.token_type().clone()).collect();
+        assert_eq!(rebuilt, expected);
+    }
+}
@@ -0,0 +1,100 @@
+//! The fingerprint module hashes token streams
+//! for caching and change detection
+
+use super::lexer::{lex, LexError, TokenType};
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// A 64 bit FNV-1a hash, which gives the same result on every platform and release
+struct Fnv(u64);
+
+impl Fnv {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    /// Write the length first, so adjacent payloads cannot run together
+    fn write_payload(&mut self, bytes: &[u8]) {
+        self.write(&(bytes.len() as u64).to_le_bytes());
+        self.write(bytes);
+    }
+}
+
+/// Hash the kinds and payloads of the tokens in the source.
+///
+/// Positions are left out, so sources that differ only in spacing, comments or
+/// blank lines have the same fingerprint. Changing the indentation of a block
+/// still changes the `Indent` and `Dedent` tokens and so the fingerprint. The
+/// hash is stable, so it can be stored by build tools.
+///
+/// ```
+/// extern crate oxy_python;
+/// use oxy_python::parser::fingerprint;
+/// assert_eq!(fingerprint("a+b").unwrap(), fingerprint("a + b  # sum").unwrap());
+/// ```
+pub fn fingerprint(source: &str) -> Result<u64, LexError> {
+    let mut hasher = Fnv(FNV_OFFSET_BASIS);
+    for token in lex(source)? {
+        let token_type = token.token_type();
+        hasher.write_payload(token_type.kind_name().as_bytes());
+        match token_type {
+            TokenType::Name(s) | TokenType::Str(s) | TokenType::FString(s) | TokenType::Op(s) => {
+                hasher.write_payload(s.as_bytes())
+            }
+            TokenType::Bytes(bytes) => hasher.write_payload(bytes),
+            TokenType::Int(value) => hasher.write_payload(&value.to_le_bytes()),
+            TokenType::Float(value) | TokenType::Imaginary(value) => {
+                hasher.write_payload(&value.to_bits().to_le_bytes())
+            }
+            TokenType::Error(c) => hasher.write_payload(&u32::from(*c).to_le_bytes()),
+            _ => {}
+        }
+    }
+    Ok(hasher.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_whitespace_is_ignored() {
+        assert_eq!(fingerprint("a+b").unwrap(), fingerprint("a + b").unwrap());
+        assert_eq!(
+            fingerprint("f(x,\n  y)\n").unwrap(),
+            fingerprint("# call\nf( x, y )\n\n").unwrap()
+        );
+        assert_eq!(
+            fingerprint("if a:\n  b\n").unwrap(),
+            fingerprint("if a:\n        b\n").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_changes_are_detected() {
+        assert_ne!(fingerprint("a+b").unwrap(), fingerprint("a-b").unwrap());
+        assert_ne!(fingerprint("ab").unwrap(), fingerprint("a b").unwrap());
+        assert_ne!(fingerprint("'a'").unwrap(), fingerprint("b'a'").unwrap());
+        assert_ne!(fingerprint("1").unwrap(), fingerprint("1.0").unwrap());
+        assert_ne!(
+            fingerprint("if a:\n  b\nc\n").unwrap(),
+            fingerprint("if a:\n  b\n  c\n").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_stable_value() {
+        // The empty source hashes to the FNV-1a offset basis
+        assert_eq!(fingerprint("").unwrap(), FNV_OFFSET_BASIS);
+        assert_eq!(fingerprint("").unwrap(), fingerprint("\n  \n").unwrap());
+    }
+
+    #[test]
+    fn test_lex_error() {
+        assert!(fingerprint("a $").is_err());
+    }
+}
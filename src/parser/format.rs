@@ -0,0 +1,915 @@
+//! The format module holds functions that rewrite
+//! python source using the lexer
+
+use std::collections::HashMap;
+
+use super::lexer::{decode_hex_escape, lex, LexError, Location, Token, TokenCategory, TokenType};
+use super::source_map::SourceMap;
+
+/// Rewrite the indentation of every logical line to `to_spaces` spaces per level.
+///
+/// The levels come from the lexer, so tabs and mixed indentation are resolved
+/// the same way the lexer resolves them. Continuation lines, lines inside strings,
+/// blank lines and comment only lines are left exactly as they were.
+///
+/// ```
+/// extern crate oxy_python;
+/// use oxy_python::parser::normalize_indentation;
+/// let result = normalize_indentation("if a:\n\tb\n", 4).unwrap();
+/// assert_eq!(result, "if a:\n    b\n");
+/// ```
+pub fn normalize_indentation(source: &str, to_spaces: u8) -> Result<String, LexError> {
+    let tokens = lex(source)?;
+
+    // The indentation level of each physical line that starts a logical line
+    let mut levels: HashMap<u64, usize> = HashMap::new();
+    let mut level = 0;
+    let mut line_start = true;
+    for token in &tokens {
+        match token.token_type() {
+            TokenType::Indent => level += 1,
+            TokenType::Dedent => level -= 1,
+            TokenType::Newline => line_start = true,
+            _ if line_start => {
+                levels.insert(token.start().line(), level);
+                line_start = false;
+            }
+            _ => {}
+        }
+    }
+
+    let mut result = String::with_capacity(source.len());
+    for (index, text) in source.split_inclusive('\n').enumerate() {
+        match levels.get(&(index as u64 + 1)) {
+            Some(level) => {
+                let indent = level * to_spaces as usize;
+                result.push_str(&" ".repeat(indent));
+                result.push_str(text.trim_start_matches([' ', '\t', '\x0c']));
+            }
+            None => result.push_str(text),
+        }
+    }
+
+    Ok(result)
+}
+
+/// Rewrite single quoted strings to use the `prefer` quote where that is safe.
+///
+/// A string is left alone if its text has an unescaped `prefer` quote in it,
+/// which also keeps f-strings with quoted strings in their expressions intact.
+/// Triple quoted strings are never changed. `prefer` should be `'` or `"`,
+/// any other character leaves the source as it was.
+///
+/// ```
+/// extern crate oxy_python;
+/// use oxy_python::parser::normalize_quotes;
+/// assert_eq!(normalize_quotes("x = 'a'", '"').unwrap(), "x = \"a\"");
+/// ```
+pub fn normalize_quotes(source: &str, prefer: char) -> Result<String, LexError> {
+    let tokens = lex(source)?;
+    if prefer != '\'' && prefer != '"' {
+        return Ok(source.to_owned());
+    }
+
+    let source_map = SourceMap::new(source);
+    let mut result = String::with_capacity(source.len());
+    let mut cursor = 0;
+    for token in &tokens {
+        match token.token_type() {
+            TokenType::Str(_) | TokenType::Bytes(_) | TokenType::FString(_) => {}
+            _ => continue,
+        }
+        let range = source_map.span_to_byte_range(&token.span());
+        if let Some(requoted) = requote(&source[range.clone()], prefer) {
+            result.push_str(&source[cursor..range.start]);
+            result.push_str(&requoted);
+            cursor = range.end;
+        }
+    }
+    result.push_str(&source[cursor..]);
+
+    Ok(result)
+}
+
+/// The string literal `text` written with `prefer` quotes, if it can be safely changed
+fn requote(text: &str, prefer: char) -> Option<String> {
+    let quote_at = text.find(['\'', '"'])?;
+    let (prefix, quoted) = text.split_at(quote_at);
+    let quote = quoted.chars().next()?;
+    let triple: String = [quote; 3].iter().collect();
+    if quote == prefer || quoted.starts_with(&triple) || quoted.len() < 2 {
+        return None;
+    }
+
+    let body = &quoted[1..quoted.len() - 1];
+    let mut escaped = false;
+    for c in body.chars() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == prefer {
+            return None;
+        }
+    }
+
+    Some(format!("{}{}{}{}", prefix, prefer, body, prefer))
+}
+
+/// How `normalize_string_escapes` writes the characters of a string
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EscapePolicy {
+    /// Write every character that is not a control character as itself,
+    /// so `'\x41'` becomes `'A'` and `'é'` becomes `'é'`
+    Minimal,
+    /// Write only printable ASCII as itself and escape everything else,
+    /// so `'é'` becomes `'\xe9'`
+    Ascii,
+}
+
+/// Rewrite the escapes in every string and bytes literal to follow `policy`.
+///
+/// Each literal is written again from its value, keeping its prefix and quotes.
+/// Quotes, backslashes and control characters are always escaped, with the
+/// short forms such as `\n` where there is one, and line breaks in triple
+/// quoted strings are written as line breaks. Raw strings, f-strings and
+/// literals with an escape the lexer does not decode, such as `\N{...}`,
+/// are left as written.
+///
+/// ```
+/// extern crate oxy_python;
+/// use oxy_python::parser::{normalize_string_escapes, EscapePolicy};
+/// let result = normalize_string_escapes("x = '\\x41'", EscapePolicy::Minimal).unwrap();
+/// assert_eq!(result, "x = 'A'");
+/// ```
+pub fn normalize_string_escapes(source: &str, policy: EscapePolicy) -> Result<String, LexError> {
+    let tokens = lex(source)?;
+    let source_map = SourceMap::new(source);
+
+    let mut result = String::with_capacity(source.len());
+    let mut cursor = 0;
+    for token in &tokens {
+        let kind = match token.string_kind() {
+            Some(kind) if !kind.prefix.raw => kind,
+            _ => continue,
+        };
+        let range = source_map.span_to_byte_range(&token.span());
+        let text = &source[range.clone()];
+        // The prefix is copied so its letters keep their case
+        let (prefix, quoted) = text.split_at(text.find(kind.quote).unwrap());
+        let fence = &quoted[..if kind.triple { 3 } else { 1 }];
+        // The value of such a literal holds the escape as written, so writing it
+        // again would escape its backslash and change the meaning
+        if has_undecoded_escape(&quoted[fence.len()..], kind.prefix.bytes) {
+            continue;
+        }
+        let body = match token.token_type() {
+            TokenType::Str(value) => escape_str(value, kind.quote, kind.triple, policy),
+            TokenType::Bytes(bytes) => escape_bytes(bytes, kind.quote, kind.triple),
+            _ => continue,
+        };
+
+        result.push_str(&source[cursor..range.start]);
+        result.push_str(prefix);
+        result.push_str(fence);
+        result.push_str(&body);
+        result.push_str(fence);
+        cursor = range.end;
+    }
+    result.push_str(&source[cursor..]);
+
+    Ok(result)
+}
+
+/// Checks if the text of a literal after its opening quotes has an escape that
+/// the lexer keeps as written rather than decoding
+fn has_undecoded_escape(text: &str, bytes: bool) -> bool {
+    let mut chars = text.char_indices();
+    while let Some((_, c)) = chars.next() {
+        if c != '\\' {
+            continue;
+        }
+        let (index, escaped) = match chars.next() {
+            Some(next) => next,
+            None => return false,
+        };
+        let length = match escaped {
+            '\n' | 'n' | 't' | 'r' | 'a' | 'b' | 'f' | 'v' | '\\' | '\'' | '"' | '0'..='7' => {
+                continue
+            }
+            'x' => 2,
+            'u' if !bytes => 4,
+            'U' if !bytes => 8,
+            _ => return true,
+        };
+        let digits = &text[index + 1..];
+        if digits.get(..length).and_then(decode_hex_escape).is_none() {
+            return true;
+        }
+        chars.nth(length - 1);
+    }
+    false
+}
+
+/// The body of a string literal with this value
+fn escape_str(value: &str, quote: char, triple: bool, policy: EscapePolicy) -> String {
+    let mut text = String::with_capacity(value.len());
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => text.push_str("\\\\"),
+            '\n' if triple => text.push('\n'),
+            '\n' => text.push_str("\\n"),
+            '\t' => text.push_str("\\t"),
+            '\r' => text.push_str("\\r"),
+            // A quote in a triple quoted string only needs escaping where it could
+            // run into another quote or the closing fence
+            _ if c == quote && (!triple || chars.peek().is_none_or(|next| *next == quote)) => {
+                text.push('\\');
+                text.push(c);
+            }
+            _ if c.is_control() || (policy == EscapePolicy::Ascii && !c.is_ascii()) => {
+                let code = c as u32;
+                if code <= 0xff {
+                    text.push_str(&format!("\\x{:02x}", code));
+                } else if code <= 0xffff {
+                    text.push_str(&format!("\\u{:04x}", code));
+                } else {
+                    text.push_str(&format!("\\U{:08x}", code));
+                }
+            }
+            _ => text.push(c),
+        }
+    }
+    text
+}
+
+/// The body of a bytes literal with this value, which can only hold ASCII
+fn escape_bytes(bytes: &[u8], quote: char, triple: bool) -> String {
+    let mut text = String::with_capacity(bytes.len());
+    for (index, byte) in bytes.iter().enumerate() {
+        let c = char::from(*byte);
+        match c {
+            '\\' => text.push_str("\\\\"),
+            '\n' if triple => text.push('\n'),
+            '\n' => text.push_str("\\n"),
+            '\t' => text.push_str("\\t"),
+            '\r' => text.push_str("\\r"),
+            _ if c == quote
+                && (!triple || bytes.get(index + 1).is_none_or(|next| *next == *byte)) =>
+            {
+                text.push('\\');
+                text.push(c);
+            }
+            ' '..='~' => text.push(c),
+            _ => text.push_str(&format!("\\x{:02x}", byte)),
+        }
+    }
+    text
+}
+
+/// Rewrite the source with as little whitespace as keeps its meaning.
+///
+/// Comments and blank lines are dropped, each logical line is written on one
+/// physical line, and every run of spaces between tokens becomes a single
+/// space. Tokens that touched stay touching. The indentation of each logical
+/// line is kept exactly as written, and tokens are copied from the source, so
+/// strings and numbers keep their spelling.
+///
+/// ```
+/// extern crate oxy_python;
+/// use oxy_python::parser::minify;
+/// assert_eq!(minify("x  =   1   #c").unwrap(), "x = 1");
+/// ```
+pub fn minify(source: &str) -> Result<String, LexError> {
+    let tokens = lex(source)?;
+    let source_map = SourceMap::new(source);
+
+    let mut result = String::with_capacity(source.len());
+    // The end of the last token written on this line, `None` at the start of a line
+    let mut previous_end: Option<usize> = None;
+    for token in &tokens {
+        match token.token_type() {
+            TokenType::Indent | TokenType::Dedent => continue,
+            TokenType::Newline => {
+                result.push('\n');
+                previous_end = None;
+                continue;
+            }
+            _ => {}
+        }
+        let range = source_map.span_to_byte_range(&token.span());
+        match previous_end {
+            None => {
+                let line_start = source_map.byte_offset(&Location::new(token.start().line(), 1));
+                result.push_str(&source[line_start..range.start]);
+            }
+            Some(end) if end < range.start => result.push(' '),
+            Some(_) => {}
+        }
+        result.push_str(&source[range.clone()]);
+        previous_end = Some(range.end);
+    }
+
+    Ok(result)
+}
+
+/// Format the source: 4 space indentation, double quotes where that is safe,
+/// and the usual spacing between tokens.
+///
+/// Line breaks are kept where they were, and so are comments, which are put
+/// two spaces after the code they follow. Trailing whitespace is removed and
+/// the file ends with one line break. Lines that continue a logical line keep
+/// their indentation. Formatting is idempotent: formatting the result again
+/// changes nothing.
+///
+/// ```
+/// extern crate oxy_python;
+/// use oxy_python::parser::format_source;
+/// let result = format_source("def  f( a,b = 1 ) :\n  return a+b # sum\n").unwrap();
+/// assert_eq!(result, "def f(a, b=1):\n    return a + b  # sum\n");
+/// ```
+pub fn format_source(source: &str) -> Result<String, LexError> {
+    let spaced = respace(source)?;
+    let quoted = normalize_quotes(&spaced, '"')?;
+    normalize_indentation(&quoted, 4)
+}
+
+/// An open bracket, while respacing the tokens inside it
+struct OpenBracket {
+    bracket: TokenType,
+    /// The parameter being written has an annotation, so its `=` is spaced
+    annotated: bool,
+}
+
+/// What `space_between` needs to know about the tokens before the gap
+struct SpaceContext<'a> {
+    /// The innermost open bracket
+    bracket: Option<&'a TokenType>,
+    annotated: bool,
+    /// The previous token is a unary operator or a decorator's `@`
+    after_unary: bool,
+    /// The previous token is the colon of a lambda
+    after_lambda_colon: bool,
+    /// The tokens are the parameters of a lambda
+    in_lambda: bool,
+    /// The tokens are the module of a `from` import, before its `import`
+    in_from_import: bool,
+}
+
+/// Rewrite the spacing between the tokens on each line, keeping line breaks
+fn respace(source: &str) -> Result<String, LexError> {
+    let tokens = lex(source)?;
+    let source_map = SourceMap::new(source);
+
+    let mut result = String::with_capacity(source.len());
+    let mut cursor = 0;
+    // The previous token on the same logical line
+    let mut previous: Option<&TokenType> = None;
+    let mut brackets: Vec<OpenBracket> = Vec::new();
+    // The bracket depth of each lambda whose colon has not been seen yet
+    let mut lambdas: Vec<usize> = Vec::new();
+    let mut after_unary = false;
+    let mut after_lambda_colon = false;
+    let mut in_from_import = false;
+
+    for token in &tokens {
+        let token_type = token.token_type();
+        match token_type {
+            TokenType::Newline => {
+                previous = None;
+                in_from_import = false;
+                continue;
+            }
+            TokenType::Indent | TokenType::Dedent | TokenType::Nl => continue,
+            _ => {}
+        }
+
+        let range = source_map.span_to_byte_range(&token.span());
+        let gap = &source[cursor..range.start];
+        match previous {
+            Some(before) if !gap.contains('\n') => {
+                let context = SpaceContext {
+                    bracket: brackets.last().map(|open| &open.bracket),
+                    annotated: brackets.last().is_some_and(|open| open.annotated),
+                    after_unary,
+                    after_lambda_colon,
+                    in_lambda: lambdas.last() == Some(&brackets.len()),
+                    in_from_import,
+                };
+                if space_between(before, token_type, &context) {
+                    result.push(' ');
+                }
+            }
+            _ => tidy_gap(gap, cursor > 0, &mut result),
+        }
+        result.push_str(&source[range.clone()]);
+        cursor = range.end;
+
+        after_unary = is_unary(previous, token_type);
+        after_lambda_colon = false;
+        match token_type {
+            TokenType::LeftParen | TokenType::LeftBracket | TokenType::LeftBrace => {
+                brackets.push(OpenBracket {
+                    bracket: token_type.clone(),
+                    annotated: false,
+                })
+            }
+            TokenType::RightParen | TokenType::RightBracket | TokenType::RightBrace => {
+                brackets.pop();
+            }
+            TokenType::Lambda => lambdas.push(brackets.len()),
+            TokenType::From if previous.is_none() => in_from_import = true,
+            TokenType::Import => in_from_import = false,
+            TokenType::Colon if lambdas.last() == Some(&brackets.len()) => {
+                lambdas.pop();
+                after_lambda_colon = true;
+            }
+            TokenType::Colon | TokenType::Comma => {
+                if let Some(open) = brackets.last_mut() {
+                    open.annotated = token_type == &TokenType::Colon;
+                }
+            }
+            _ => {}
+        }
+        previous = Some(token_type);
+    }
+
+    let mut end = String::new();
+    tidy_gap(&source[cursor..], cursor > 0, &mut end);
+    result.push_str(end.trim_end());
+    if !result.is_empty() {
+        result.push('\n');
+    }
+    Ok(result)
+}
+
+/// Write the text between two tokens that are on different lines, which holds
+/// only whitespace, comments and backslashes, without trailing whitespace.
+/// The last line of the gap is the indentation of the next token, which is kept.
+fn tidy_gap(gap: &str, after_token: bool, result: &mut String) {
+    let mut lines = gap.split('\n').peekable();
+    let mut first = true;
+    while let Some(line) = lines.next() {
+        if lines.peek().is_none() {
+            result.push_str(line);
+            break;
+        }
+        let text = line.trim_end();
+        if first && after_token {
+            // The rest of the line after the previous token
+            let rest = text.trim_start();
+            if !rest.is_empty() {
+                result.push_str(if rest.starts_with('#') { "  " } else { " " });
+                result.push_str(rest);
+            }
+        } else {
+            result.push_str(text);
+        }
+        result.push('\n');
+        first = false;
+    }
+}
+
+/// Checks if the token is an operator used with one operand, such as `-x`,
+/// `*args` or the `@` of a decorator
+fn is_unary(previous: Option<&TokenType>, token_type: &TokenType) -> bool {
+    let previous = match previous {
+        Some(previous) => previous,
+        None => {
+            return matches!(
+                token_type,
+                TokenType::Minus
+                    | TokenType::Plus
+                    | TokenType::Tilde
+                    | TokenType::Star
+                    | TokenType::StarStar
+                    | TokenType::At
+            )
+        }
+    };
+    match token_type {
+        TokenType::Tilde => true,
+        TokenType::Minus | TokenType::Plus | TokenType::Star | TokenType::StarStar => {
+            match previous {
+                TokenType::True | TokenType::False | TokenType::None => false,
+                TokenType::LeftParen
+                | TokenType::LeftBracket
+                | TokenType::LeftBrace
+                | TokenType::Comma
+                | TokenType::Colon
+                | TokenType::Semicolon
+                | TokenType::Arrow => true,
+                _ => matches!(
+                    previous.category(),
+                    TokenCategory::Operator | TokenCategory::Keyword
+                ),
+            }
+        }
+        _ => false,
+    }
+}
+
+/// Checks if formatted source has a space between two tokens on the same line
+fn space_between(previous: &TokenType, next: &TokenType, context: &SpaceContext) -> bool {
+    use TokenType::*;
+    let in_slice = context.bracket == Some(&LeftBracket);
+    let in_call = context.bracket == Some(&LeftParen) && !context.annotated;
+    match (previous, next) {
+        _ if context.after_unary => false,
+        (LeftParen, _) | (LeftBracket, _) | (LeftBrace, _) => false,
+        (_, RightParen) | (_, RightBracket) | (_, RightBrace) => false,
+        (_, Comma) | (_, Semicolon) | (_, Colon) => false,
+        (Comma, _) | (Semicolon, _) => true,
+        (Colon, _) => context.after_lambda_colon || !in_slice,
+        (From, Dot) | (Import, Dot) | (Dot, Import) => true,
+        // `...` is three dots of a relative import, which join the module name
+        (Ellipsis, _) if context.in_from_import => next == &Import,
+        (Dot, _) | (_, Dot) => false,
+        // Checked before brackets, so `f(a=(1))` keeps the value next to its `=`
+        (Equal, _) if in_call || context.in_lambda => false,
+        (_, LeftParen) | (_, LeftBracket) => !matches!(
+            previous.category(),
+            TokenCategory::Name | TokenCategory::Literal | TokenCategory::Bracket
+        ),
+        (Equal, _) | (_, Equal) => !in_call && !context.in_lambda,
+        _ => true,
+    }
+}
+
+/// Rebuild source text from tokens, keeping every line where it was.
+///
+/// Tokens are placed back at their lines and columns, so the blank lines
+/// between statements survive. Blank lines after the last token need the
+/// `Nl` tokens produced with `LexerOptions::emit_nl_tokens`, and with them
+/// every line break is reproduced exactly. Indentation and other gaps are written
+/// as spaces, lines joined by a backslash are joined by one again, and
+/// literals are written in a standard spelling. Comments are not tokens, so
+/// their lines come back blank.
+///
+/// ```
+/// extern crate oxy_python;
+/// use oxy_python::parser::{lex_with_options, reconstruct, LexerOptions};
+/// let options = LexerOptions {
+///     emit_nl_tokens: true,
+///     ..LexerOptions::default()
+/// };
+/// let source = "a = 1\n\nb = 2\n";
+/// let tokens = lex_with_options(source, &options).unwrap();
+/// assert_eq!(reconstruct(&tokens), source);
+/// ```
+pub fn reconstruct(tokens: &[Token]) -> String {
+    let mut result = String::new();
+    let mut line = 1;
+    let mut column = 1;
+
+    for token in tokens {
+        match token.token_type() {
+            TokenType::Newline | TokenType::Nl => {
+                result.push('\n');
+                line += 1;
+                column = 1;
+            }
+            TokenType::Indent | TokenType::Dedent => {}
+            _ => {
+                let start = token.start();
+                while line < start.line() {
+                    // Lines skipped mid-statement were joined with a backslash
+                    if column == 1 {
+                        result.push('\n');
+                    } else {
+                        result.push_str("\\\n");
+                    }
+                    line += 1;
+                    column = 1;
+                }
+                while column < start.column() {
+                    result.push(' ');
+                    column += 1;
+                }
+                result.push_str(&token_text(token));
+                line = token.end().line();
+                column = token.end().column() + 1;
+            }
+        }
+    }
+
+    result
+}
+
+/// Python source text for a token
+fn token_text(token: &Token) -> String {
+    let token_type = token.token_type();
+    if let Some(text) = token_type.text() {
+        return text.to_owned();
+    }
+    // A raw literal holds its text as written, which only means the same with the prefix
+    if token.string_kind().is_some_and(|kind| kind.prefix.raw) {
+        match token_type {
+            TokenType::Str(text) => return quote_raw(text, "r"),
+            TokenType::FString(text) => return quote_raw(text, "rf"),
+            TokenType::Bytes(bytes) => return quote_raw(&String::from_utf8_lossy(bytes), "rb"),
+            _ => {}
+        }
+    }
+    match token_type {
+        TokenType::Name(name) | TokenType::Op(name) => name.clone(),
+        TokenType::Str(value) => quote(value, ""),
+        TokenType::FString(text) => quote_raw(text, "f"),
+        TokenType::Bytes(bytes) => {
+            let mut text = String::from("b'");
+            for byte in bytes {
+                match byte {
+                    b'\\' => text.push_str("\\\\"),
+                    b'\'' => text.push_str("\\'"),
+                    b'\n' => text.push_str("\\n"),
+                    b'\t' => text.push_str("\\t"),
+                    b'\r' => text.push_str("\\r"),
+                    b' '..=b'~' => text.push(*byte as char),
+                    _ => text.push_str(&format!("\\x{:02x}", byte)),
+                }
+            }
+            text.push('\'');
+            text
+        }
+        TokenType::Int(value) => value.to_string(),
+        TokenType::Float(value) => float_text(*value),
+        TokenType::Imaginary(value) => float_text(*value) + "j",
+        TokenType::Error(c) => c.to_string(),
+        _ => String::new(),
+    }
+}
+
+/// Write a float so Python reads back the same value
+fn float_text(value: f64) -> String {
+    if value.is_infinite() {
+        // Too large for a float, which Python also reads as infinity
+        "1e999".to_owned()
+    } else {
+        format!("{:?}", value)
+    }
+}
+
+/// Quote a decoded string value, using triple quotes if it spans several lines
+fn quote(value: &str, prefix: &str) -> String {
+    let quote = if value.contains('\'') && !value.contains('"') {
+        '"'
+    } else {
+        '\''
+    };
+    let triple = value.contains('\n');
+    let fence = if triple {
+        quote.to_string().repeat(3)
+    } else {
+        quote.to_string()
+    };
+
+    let mut text = format!("{}{}", prefix, fence);
+    for c in value.chars() {
+        match c {
+            '\\' => text.push_str("\\\\"),
+            '\n' => text.push('\n'),
+            '\t' => text.push_str("\\t"),
+            '\r' => text.push_str("\\r"),
+            _ if c == quote => {
+                text.push('\\');
+                text.push(c);
+            }
+            _ if c.is_control() => text.push_str(&format!("\\x{:02x}", c as u32)),
+            _ => text.push(c),
+        }
+    }
+    text.push_str(&fence);
+    text
+}
+
+/// Quote text that was kept as written, choosing quotes it does not contain
+fn quote_raw(text: &str, prefix: &str) -> String {
+    let fence = if text.contains('\n') || (text.contains('\'') && text.contains('"')) {
+        "\'\'\'"
+    } else if text.contains('\'') {
+        "\""
+    } else {
+        "\'"
+    };
+    format!("{}{}{}{}", prefix, fence, text, fence)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{fingerprint, lex_with_options, LexerOptions};
+
+    fn round_trip(source: &str) -> String {
+        let options = LexerOptions {
+            emit_nl_tokens: true,
+            ..LexerOptions::default()
+        };
+        reconstruct(&lex_with_options(source, &options).unwrap())
+    }
+
+    #[test]
+    fn test_normalize_quotes() {
+        assert_eq!(normalize_quotes("'a'", '"').unwrap(), "\"a\"");
+        let source = "'he said \"hi\"'";
+        assert_eq!(normalize_quotes(source, '"').unwrap(), source);
+        assert_eq!(
+            normalize_quotes(r"x = b'\'' + r'\d' + 'é'", '"').unwrap(),
+            r#"x = b"\'" + r"\d" + "é""#
+        );
+        assert_eq!(
+            normalize_quotes(r#""a" + "b'""#, '\'').unwrap(),
+            r#"'a' + "b'""#
+        );
+    }
+
+    #[test]
+    fn test_normalize_quotes_leaves_some_strings() {
+        let source = r#"f'{x["k"]}' + f'{y}'"#;
+        assert_eq!(
+            normalize_quotes(source, '"').unwrap(),
+            r#"f'{x["k"]}' + f"{y}""#
+        );
+        let source = r#"'''a''' + 'b\"'"#;
+        assert_eq!(normalize_quotes(source, '"').unwrap(), r#"'''a''' + "b\"""#);
+        assert_eq!(normalize_quotes("'a'", 'x').unwrap(), "'a'");
+        assert!(normalize_quotes("'a", '"').is_err());
+    }
+
+    #[test]
+    fn test_normalize_string_escapes_minimal() {
+        let normalize = |source| normalize_string_escapes(source, EscapePolicy::Minimal).unwrap();
+        assert_eq!(normalize(r"x = '\x41\u00e9'"), r"x = 'Aé'");
+        // The lexer keeps these escapes as written, so the literals are kept too
+        assert_eq!(normalize(r"x = '\x41\N{BULLET}'"), r"x = '\x41\N{BULLET}'");
+        assert_eq!(
+            normalize(r"'\x4' + '\d' + b'\u0041'"),
+            r"'\x4' + '\d' + b'\u0041'"
+        );
+        assert_eq!(normalize(r"'\\N' + '\x41'"), r"'\\N' + 'A'");
+        assert_eq!(normalize(r"'\x+f' + '\u+041'"), r"'\x+f' + '\u+041'");
+        assert_eq!(normalize(r#"'\U0001f600 \x00 \'"'"#), r#"'😀 \x00 \'"'"#);
+        assert_eq!(normalize("'''a\\nb'''"), "'''a\nb'''");
+        assert_eq!(normalize(r#""""say "hi\x22""""#), r#""""say "hi\"""""#);
+        assert_eq!(normalize(r"b'\x41\x00\xff'"), r"b'A\x00\xff'");
+        // Raw strings and f-strings are left as written
+        assert_eq!(normalize(r"r'\x41' + f'\x41{a}'"), r"r'\x41' + f'\x41{a}'");
+    }
+
+    #[test]
+    fn test_normalize_string_escapes_ascii() {
+        let normalize = |source| normalize_string_escapes(source, EscapePolicy::Ascii).unwrap();
+        assert_eq!(normalize("'é'"), r"'\xe9'");
+        assert_eq!(normalize("\"ü ☃ 😀\""), r#""\xfc \u2603 \U0001f600""#);
+        assert_eq!(normalize(r"'\x41'"), "'A'");
+        assert_eq!(normalize("'plain'  # ü"), "'plain'  # ü");
+    }
+
+    #[test]
+    fn test_normalize_string_escapes_keeps_values() {
+        let source = "a = '\\x41\\'é\\t'\nb = \"\"\"x \"\\\"\"\nz\"\"\"\nc = b'\\'\\x80'\n";
+        for policy in [EscapePolicy::Minimal, EscapePolicy::Ascii].iter() {
+            let normalized = normalize_string_escapes(source, *policy).unwrap();
+            let types = |source: &str| -> Vec<TokenType> {
+                lex(source)
+                    .unwrap()
+                    .iter()
+                    .map(|token| token.token_type().clone())
+                    .collect()
+            };
+            assert_eq!(types(&normalized), types(source), "{:?}", normalized);
+            assert_eq!(
+                normalize_string_escapes(&normalized, *policy).unwrap(),
+                normalized
+            );
+        }
+    }
+
+    #[test]
+    fn test_minify() {
+        assert_eq!(minify("x  =   1   #c").unwrap(), "x = 1");
+        assert_eq!(minify("f(a,b)  +  g( c )\n").unwrap(), "f(a,b) + g( c )\n");
+        assert_eq!(minify("").unwrap(), "");
+        assert!(minify("a $").is_err());
+    }
+
+    #[test]
+    fn test_minify_keeps_indentation_and_strings() {
+        let source = "# header\n\nif a:\n\tb = 'x  y'   # note\n\n\tif c:\n\t    d = (1,\n  2)\ne = f'{a}  {b}'\n";
+        let expected = "if a:\n\tb = 'x  y'\n\tif c:\n\t    d = (1, 2)\ne = f'{a}  {b}'\n";
+        let minified = minify(source).unwrap();
+        assert_eq!(minified, expected);
+        assert_eq!(
+            fingerprint(&minified).unwrap(),
+            fingerprint(source).unwrap()
+        );
+
+        assert_eq!(minify("a = 1 + \\\n    2\n").unwrap(), "a = 1 + 2\n");
+    }
+
+    #[test]
+    fn test_reconstruct_blank_lines() {
+        let source = "import os\n\n\ndef f(x):\n    return x + 1\n\n\nf(2)\n";
+        assert_eq!(round_trip(source), source);
+        let source = "\n\na = [\n    1,\n\n    2,\n]\nb = 'x'\n";
+        assert_eq!(round_trip(source), source);
+    }
+
+    #[test]
+    fn test_reconstruct_literals() {
+        let source = "a = \"it's\" + 'q\\\\' + b'\\x00y' + 0x10 + 1.5 + 2j\n";
+        assert_eq!(
+            round_trip(source),
+            "a = \"it's\" + 'q\\\\' + b'\\x00y' + 16 + 1.5 + 2.0j\n"
+        );
+        let source = "s = '''one\ntwo'''\nt = f'{x!r}'\n";
+        assert_eq!(round_trip(source), source);
+        // Raw literals keep their prefix, so backslashes mean the same
+        let source = r"a = rf'\d{x}' + r'\d' + rb'\d' + fr'\n'";
+        assert_eq!(
+            round_trip(source),
+            r"a = rf'\d{x}' + r'\d' + rb'\d' + rf'\n'"
+        );
+        assert_eq!(round_trip(r"Rb'\''"), r#"rb"\'""#);
+    }
+
+    #[test]
+    fn test_reconstruct_comments_and_continuations() {
+        assert_eq!(
+            round_trip("# note\na = 1 + \\\n    2\n"),
+            "\na = 1 +\\\n    2\n"
+        );
+        let without_nl = lex("a\n\nb\n\n").unwrap();
+        assert_eq!(reconstruct(&without_nl), "a\n\nb\n");
+    }
+
+    #[test]
+    fn test_tabs_to_spaces() {
+        let source = "if a:\n\tb\n\tif c:\n\t\td\n\n\te # \tcomment\nf\n";
+        let expected = "if a:\n    b\n    if c:\n        d\n\n    e # \tcomment\nf\n";
+        assert_eq!(normalize_indentation(source, 4).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_mixed_indentation() {
+        let source = "if a:\n        b\n\tc";
+        assert_eq!(normalize_indentation(source, 2).unwrap(), "if a:\n  b\n  c");
+    }
+
+    #[test]
+    fn test_string_contents_untouched() {
+        let source = "if a:\n\t'''\n\tinside\n\t\t'''\n";
+        let expected = "if a:\n    '''\n\tinside\n\t\t'''\n";
+        assert_eq!(normalize_indentation(source, 4).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_lex_error() {
+        assert!(normalize_indentation("if a:\n    b\n  c", 4).is_err());
+    }
+
+    #[test]
+    fn test_format_source_spacing() {
+        let format = |source| format_source(source).unwrap();
+        assert_eq!(format("x=-1\n"), "x = -1\n");
+        assert_eq!(format("a [ 1 : -1 ] , b [ :: 2 ]\n"), "a[1:-1], b[::2]\n");
+        assert_eq!(format("f( * args , ** kw )\n"), "f(*args, **kw)\n");
+        assert_eq!(
+            format("from . import x\nfrom ..pkg import y\n"),
+            "from . import x\nfrom ..pkg import y\n"
+        );
+        assert_eq!(
+            format("from ...pkg import y\nfrom .... import z\nx = ... if a else ...\n"),
+            "from ...pkg import y\nfrom .... import z\nx = ... if a else ...\n"
+        );
+        assert_eq!(format("f(a=(1), b=[2])\n"), "f(a=(1), b=[2])\n");
+        assert_eq!(format("x=(1)\ny=[2]\n"), "x = (1)\ny = [2]\n");
+        assert_eq!(format("@ dec\ndef f( ) : pass\n"), "@dec\ndef f(): pass\n");
+        assert_eq!(
+            format("if not(a)or-b :\n  pass\n"),
+            "if not (a) or -b:\n    pass\n"
+        );
+        assert_eq!(format("d = { 'a' :1 , **e }\n"), "d = {\"a\": 1, **e}\n");
+        assert_eq!(
+            format("f = lambda x , y=1 :x@y\n"),
+            "f = lambda x, y=1: x @ y\n"
+        );
+        assert_eq!(
+            format("print ( 'a' [ 0 ] . upper ( ) )\n"),
+            "print(\"a\"[0].upper())\n"
+        );
+    }
+
+    #[test]
+    fn test_format_source_keeps_lines() {
+        let format = |source| format_source(source).unwrap();
+        assert_eq!(format(""), "");
+        assert_eq!(format("# only a comment   \n\n\n"), "# only a comment\n");
+        assert_eq!(format("x = 1 + \\\n      2\n"), "x = 1 + \\\n      2\n");
+        assert_eq!(format("s = '''a  \n  b'''\n"), "s = '''a  \n  b'''\n");
+        assert_eq!(
+            format("if a:\n\n    # note   \n    b=1\nc=2"),
+            "if a:\n\n    # note\n    b = 1\nc = 2\n"
+        );
+    }
+}
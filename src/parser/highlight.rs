@@ -0,0 +1,243 @@
+//! The highlight module renders python source
+//! with its tokens marked up by category
+
+use super::lexer::{lex, Token, TokenCategory, TokenType};
+use super::source_map::SourceMap;
+use std::fmt::Write;
+use std::io::{self, IsTerminal};
+use std::str::FromStr;
+
+/// Render the source as HTML with every token wrapped in a `<span>`.
+///
+/// The class of each span comes from the token category, e.g. `tok-keyword`,
+/// with literals split into `tok-string` and `tok-number`.
+/// Whitespace and comments between tokens are kept as escaped text, so the
+/// result is ready to be placed inside a `<pre>`. Source that does not lex
+/// is returned as escaped text with no markup.
+///
+/// ```
+/// extern crate oxy_python;
+/// use oxy_python::parser::highlight_html;
+/// let html = highlight_html("if a");
+/// assert!(html.starts_with("<span class=\"tok-keyword\">if</span>"));
+/// ```
+pub fn highlight_html(source: &str) -> String {
+    let tokens = match lex(source) {
+        Ok(tokens) => tokens,
+        Err(_) => return escape_html(source),
+    };
+    let map = SourceMap::new(source);
+    let mut result = String::with_capacity(source.len() * 2);
+    let mut cursor = 0;
+
+    for token in &tokens {
+        let class = match css_class(token.token_type()) {
+            Some(class) => class,
+            None => continue,
+        };
+        let start = map.byte_offset(token.start());
+        let end = map.byte_offset_after(token.end());
+
+        result.push_str(&escape_html(&source[cursor..start]));
+        result.push_str("<span class=\"");
+        result.push_str(class);
+        result.push_str("\">");
+        result.push_str(&escape_html(&source[start..end]));
+        result.push_str("</span>");
+        cursor = end;
+    }
+    result.push_str(&escape_html(&source[cursor..]));
+
+    result
+}
+
+/// The CSS class for a token, layout tokens have no text and get no class
+fn css_class(token_type: &TokenType) -> Option<&'static str> {
+    match token_type.category() {
+        TokenCategory::Operator => Some("tok-operator"),
+        TokenCategory::Bracket => Some("tok-bracket"),
+        TokenCategory::Delimiter => Some("tok-delimiter"),
+        TokenCategory::Name => Some("tok-name"),
+        TokenCategory::Literal => match token_type {
+            TokenType::Int(_) | TokenType::Float(_) | TokenType::Imaginary(_) => Some("tok-number"),
+            _ => Some("tok-string"),
+        },
+        TokenCategory::Keyword => Some("tok-keyword"),
+        TokenCategory::Error => Some("tok-error"),
+        TokenCategory::Layout => None,
+    }
+}
+
+/// When to color output meant for a terminal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorMode {
+    /// Color only when the output is a terminal
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorMode {
+    /// Checks if output should be colored, given whether it goes to a terminal
+    pub fn use_color(self, is_terminal: bool) -> bool {
+        match self {
+            ColorMode::Auto => is_terminal,
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+        }
+    }
+}
+
+impl FromStr for ColorMode {
+    type Err = String;
+
+    /// Parse the value of a `--color` flag
+    fn from_str(s: &str) -> Result<ColorMode, String> {
+        match s {
+            "auto" => Ok(ColorMode::Auto),
+            "always" => Ok(ColorMode::Always),
+            "never" => Ok(ColorMode::Never),
+            _ => Err(format!("expected auto, always or never, found {:?}", s)),
+        }
+    }
+}
+
+/// Render the tokens one per line, as `line:column-line:column` followed by the
+/// token, with the token colored by category when `color` is set.
+///
+/// ```
+/// extern crate oxy_python;
+/// use oxy_python::parser::{lex, render_tokens};
+/// let tokens = lex("if a").unwrap();
+/// assert!(render_tokens(&tokens, false).starts_with("1:1-1:2 If\n"));
+/// assert!(render_tokens(&tokens, true).starts_with("1:1-1:2 \u{1b}[35mIf\u{1b}[0m\n"));
+/// ```
+pub fn render_tokens(tokens: &[Token], color: bool) -> String {
+    let mut result = String::new();
+    for token in tokens {
+        let (start, end) = (token.start(), token.end());
+        let _ = write!(
+            result,
+            "{}:{}-{}:{} ",
+            start.line(),
+            start.column(),
+            end.line(),
+            end.column()
+        );
+        match ansi_color(token.token_type().category()).filter(|_| color) {
+            Some(code) => {
+                let _ = write!(result, "\x1b[{}m{:?}\x1b[0m", code, token.token_type());
+            }
+            None => {
+                let _ = write!(result, "{:?}", token.token_type());
+            }
+        }
+        result.push('\n');
+    }
+    result
+}
+
+/// Render the tokens as `render_tokens` does, colored when stdout is a terminal
+pub fn render_tokens_colored(tokens: &[Token]) -> String {
+    render_tokens(
+        tokens,
+        ColorMode::Auto.use_color(io::stdout().is_terminal()),
+    )
+}
+
+/// The ANSI color code for a category, names are left in the default color
+fn ansi_color(category: TokenCategory) -> Option<&'static str> {
+    match category {
+        TokenCategory::Operator => Some("33"),
+        TokenCategory::Bracket | TokenCategory::Delimiter => Some("36"),
+        TokenCategory::Name => None,
+        TokenCategory::Literal => Some("32"),
+        TokenCategory::Keyword => Some("35"),
+        TokenCategory::Error => Some("31"),
+        TokenCategory::Layout => Some("2"),
+    }
+}
+
+/// Escape the characters that are special in HTML
+fn escape_html(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&#39;"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keyword_class() {
+        let html = highlight_html("if a:\n    b # note\n");
+        assert_eq!(
+            html,
+            "<span class=\"tok-keyword\">if</span> <span class=\"tok-name\">a</span>\
+             <span class=\"tok-delimiter\">:</span>\n    <span class=\"tok-name\">b</span> # note\n"
+        );
+    }
+
+    #[test]
+    fn test_escaping() {
+        let html = highlight_html("'<&>'");
+        assert_eq!(
+            html,
+            "<span class=\"tok-string\">&#39;&lt;&amp;&gt;&#39;</span>"
+        );
+    }
+
+    #[test]
+    fn test_number_class() {
+        let html = highlight_html("1 + 2.5j + b'x'");
+        assert_eq!(
+            html,
+            "<span class=\"tok-number\">1</span> <span class=\"tok-operator\">+</span> \
+             <span class=\"tok-number\">2.5j</span> <span class=\"tok-operator\">+</span> \
+             <span class=\"tok-string\">b&#39;x&#39;</span>"
+        );
+    }
+
+    #[test]
+    fn test_invalid_source() {
+        assert_eq!(highlight_html("a < b & c $"), "a &lt; b &amp; c $");
+    }
+
+    #[test]
+    fn test_color_mode() {
+        for &is_terminal in [true, false].iter() {
+            assert!(ColorMode::Always.use_color(is_terminal));
+            assert!(!ColorMode::Never.use_color(is_terminal));
+            assert_eq!(ColorMode::Auto.use_color(is_terminal), is_terminal);
+        }
+        assert_eq!("always".parse(), Ok(ColorMode::Always));
+        assert_eq!("never".parse(), Ok(ColorMode::Never));
+        assert_eq!("auto".parse(), Ok(ColorMode::Auto));
+        assert!("yes".parse::<ColorMode>().is_err());
+    }
+
+    #[test]
+    fn test_render_tokens() {
+        let tokens = lex("x = 'a'\n").unwrap();
+        assert_eq!(
+            render_tokens(&tokens, false),
+            "1:1-1:1 Name(\"x\")\n1:3-1:3 Equal\n1:5-1:7 Str(\"a\")\n1:8-1:8 Newline\n"
+        );
+        assert_eq!(
+            render_tokens(&tokens, true),
+            "1:1-1:1 Name(\"x\")\n1:3-1:3 \x1b[33mEqual\x1b[0m\n\
+             1:5-1:7 \x1b[32mStr(\"a\")\x1b[0m\n1:8-1:8 \x1b[2mNewline\x1b[0m\n"
+        );
+    }
+}
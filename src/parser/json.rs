@@ -0,0 +1,147 @@
+//! The json module writes tokens out as JSON
+//! for debugging editor integrations
+
+use std::fmt::Write;
+
+use super::lexer::{lex, LexError, Location, TokenType};
+use super::source_map::SourceMap;
+
+/// Lex the source and dump its tokens as indented JSON, one object per token.
+///
+/// Each object has the `kind_name` of the token, its value, its start and end
+/// lines and columns, and the byte range it covers. The value is the payload
+/// of names and literals, the spelling of operators and keywords, and `null`
+/// for layout tokens.
+///
+/// ```
+/// extern crate oxy_python;
+/// use oxy_python::parser::tokens_debug_json;
+/// let json = tokens_debug_json("x").unwrap();
+/// assert!(json.contains("\"kind\": \"Name\""));
+/// ```
+pub fn tokens_debug_json(source: &str) -> Result<String, LexError> {
+    let tokens = lex(source)?;
+    let source_map = SourceMap::new(source);
+
+    let mut json = String::from("[");
+    for (index, token) in tokens.iter().enumerate() {
+        if index > 0 {
+            json.push(',');
+        }
+        let bytes = source_map.span_to_byte_range(&token.span());
+        write!(
+            json,
+            "\n  {{\n    \"kind\": \"{}\",\n    \"value\": {},\n    \"start\": {},\n    \
+             \"end\": {},\n    \"bytes\": [{}, {}]\n  }}",
+            token.token_type().kind_name(),
+            value_json(token.token_type()),
+            location_json(token.start()),
+            location_json(token.end()),
+            bytes.start,
+            bytes.end
+        )
+        .unwrap();
+    }
+    if !tokens.is_empty() {
+        json.push('\n');
+    }
+    json.push(']');
+
+    Ok(json)
+}
+
+/// The JSON for a location
+fn location_json(location: &Location) -> String {
+    format!(
+        "{{ \"line\": {}, \"column\": {} }}",
+        location.line(),
+        location.column()
+    )
+}
+
+/// The JSON for the value a token carries
+fn value_json(token_type: &TokenType) -> String {
+    if let Some(text) = token_type.text() {
+        return string_json(text);
+    }
+    match token_type {
+        TokenType::Name(text)
+        | TokenType::Str(text)
+        | TokenType::FString(text)
+        | TokenType::Op(text) => string_json(text),
+        TokenType::Bytes(bytes) => {
+            let bytes: Vec<String> = bytes.iter().map(u8::to_string).collect();
+            format!("[{}]", bytes.join(", "))
+        }
+        TokenType::Int(value) => value.to_string(),
+        TokenType::Float(value) | TokenType::Imaginary(value) if value.is_finite() => {
+            format!("{:?}", value)
+        }
+        // JSON has no infinity
+        TokenType::Float(value) | TokenType::Imaginary(value) => string_json(&value.to_string()),
+        TokenType::Error(c) => string_json(&c.to_string()),
+        _ => "null".to_owned(),
+    }
+}
+
+/// Quote and escape text as a JSON string
+fn string_json(text: &str) -> String {
+    let mut json = String::with_capacity(text.len() + 2);
+    json.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            _ if c.is_control() => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            _ => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_if_token() {
+        let json = tokens_debug_json("if x").unwrap();
+        let expected = r#"[
+  {
+    "kind": "If",
+    "value": "if",
+    "start": { "line": 1, "column": 1 },
+    "end": { "line": 1, "column": 2 },
+    "bytes": [0, 2]
+  },
+  {
+    "kind": "Name",
+    "value": "x",
+    "start": { "line": 1, "column": 4 },
+    "end": { "line": 1, "column": 4 },
+    "bytes": [3, 4]
+  }
+]"#;
+        assert_eq!(json, expected);
+    }
+
+    #[test]
+    fn test_values() {
+        assert_eq!(tokens_debug_json("").unwrap(), "[]");
+        let json = tokens_debug_json("'a\"\\n' b'\\x01' 1.5 1e999 7\n").unwrap();
+        assert!(json.contains(r#""value": "a\"\n""#));
+        assert!(json.contains(r#""value": [1]"#));
+        assert!(json.contains(r#""value": 1.5"#));
+        assert!(json.contains(r#""value": "inf""#));
+        assert!(json.contains(r#""value": 7"#));
+        assert!(serde_json::from_str::<serde_json::Value>(&json).is_ok());
+        assert!(json.contains(
+            r#""kind": "Newline",
+    "value": null"#
+        ));
+    }
+}
@@ -1,25 +1,128 @@
 //! The lexer module holds functions and structs
 //! that assist with lexing a python program
 
-use std::str::Chars;
+use super::source_map::SourceMap;
+use phf::phf_map;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::num::IntErrorKind;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::Arc;
 
-/// A location in the file
-#[derive(Debug)]
+/// A location in the file.
+///
+/// Locations are ordered by line, then by column. The byte column is left out
+/// of comparisons, so a location is the same whether or not it was filled in.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Location {
     /// The number of the line, starting with 1
     line: u64,
     /// The number of the column, starting with 1
     column: u64,
+    /// The column counted in bytes rather than characters, starting with 1
+    byte_column: Option<u64>,
 }
 
 impl Location {
-    fn new(line: u64, column: u64) -> Location {
-        Location { line, column }
+    pub(crate) fn new(line: u64, column: u64) -> Location {
+        Location {
+            line,
+            column,
+            byte_column: None,
+        }
+    }
+
+    /// The number of the line, starting with 1, or 0 with `LexerOptions::zero_based_positions`
+    pub fn line(&self) -> u64 {
+        self.line
+    }
+
+    /// The number of the column, starting with 1, or 0 with `LexerOptions::zero_based_positions`
+    pub fn column(&self) -> u64 {
+        self.column
+    }
+
+    /// The column counted in bytes, starting with 1.
+    ///
+    /// This is only known when lexing with `LexerOptions::byte_columns`, and
+    /// differs from `column` once a line has multi-byte characters before it.
+    pub fn byte_column(&self) -> Option<u64> {
+        self.byte_column
+    }
+
+    /// Move `n` columns along the line
+    pub(crate) fn advance_column(&mut self, n: u64) {
+        self.column += n;
+    }
+
+    /// Move to the first column of the next line
+    pub(crate) fn advance_line(&mut self) {
+        self.line += 1;
+        self.column = 1;
+    }
+
+    /// The location one column back, the last column of a token that ends here
+    fn previous_column(self) -> Location {
+        Location::new(self.line, self.column - 1)
+    }
+
+    /// Count from 0 instead of 1
+    fn shift_to_zero_based(&mut self) {
+        self.line -= 1;
+        self.column -= 1;
+        if let Some(byte_column) = &mut self.byte_column {
+            *byte_column -= 1;
+        }
+    }
+
+    /// What locations are compared and hashed by
+    fn key(&self) -> (u64, u64) {
+        (self.line, self.column)
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+impl PartialEq for Location {
+    fn eq(&self, other: &Location) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Location {}
+
+impl PartialOrd for Location {
+    fn partial_cmp(&self, other: &Location) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Location {
+    fn cmp(&self, other: &Location) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl Hash for Location {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
     }
 }
 
 /// Holds a lexed token and data with its position in the file
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Token {
     /// The start location of the token
     start: Location,
@@ -27,6 +130,10 @@ pub struct Token {
     end: Location,
     /// The value of the token
     token_type: TokenType,
+    /// The bytes of the source the token covers, with `LexerOptions::byte_ranges`
+    byte_range: Option<Range<usize>>,
+    /// How a string, bytes or f-string literal was written
+    string_kind: Option<StringKind>,
 }
 
 impl Token {
@@ -34,10 +141,92 @@ impl Token {
     pub fn is_type(&self, other: &TokenType) -> bool {
         other == &self.token_type
     }
+
+    /// The start location of the token
+    pub fn start(&self) -> &Location {
+        &self.start
+    }
+
+    /// The end location of the token
+    pub fn end(&self) -> &Location {
+        &self.end
+    }
+
+    /// The value of the token
+    pub fn token_type(&self) -> &TokenType {
+        &self.token_type
+    }
+
+    /// The byte offsets of the token in the source, end exclusive.
+    ///
+    /// This is only known when lexing with `LexerOptions::byte_ranges`.
+    /// `Indent` and `Dedent` tokens have an empty range where the line's text starts.
+    pub fn byte_range(&self) -> Option<Range<usize>> {
+        self.byte_range.clone()
+    }
+
+    /// The quotes and prefix of a string, bytes or f-string literal, `None` for other tokens
+    pub fn string_kind(&self) -> Option<StringKind> {
+        self.string_kind
+    }
+}
+
+/// An owned sequence of tokens, which can be collected from a `Lexer`.
+///
+/// ```
+/// extern crate oxy_python;
+/// use oxy_python::parser::{lex_iter, Tokens};
+/// let tokens: Tokens = lex_iter("a + b").collect::<Result<_, _>>().unwrap();
+/// assert_eq!(tokens.len(), 3);
+/// ```
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Tokens(Vec<Token>);
+
+impl Tokens {
+    /// Take the tokens out as a `Vec`
+    pub fn into_vec(self) -> Vec<Token> {
+        self.0
+    }
+}
+
+impl From<Vec<Token>> for Tokens {
+    fn from(tokens: Vec<Token>) -> Tokens {
+        Tokens(tokens)
+    }
+}
+
+impl std::ops::Deref for Tokens {
+    type Target = [Token];
+
+    fn deref(&self) -> &[Token] {
+        &self.0
+    }
+}
+
+impl std::iter::FromIterator<Token> for Tokens {
+    fn from_iter<I: IntoIterator<Item = Token>>(iter: I) -> Tokens {
+        Tokens(iter.into_iter().collect())
+    }
+}
+
+impl Extend<Token> for Tokens {
+    fn extend<I: IntoIterator<Item = Token>>(&mut self, iter: I) {
+        self.0.extend(iter)
+    }
+}
+
+impl IntoIterator for Tokens {
+    type Item = Token;
+    type IntoIter = std::vec::IntoIter<Token>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
 }
 
 /// A type of token with the data inside
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TokenType {
     // ---- Arithmetic Tokens ----
     /// Plus Sign
@@ -50,215 +239,4728 @@ pub enum TokenType {
     StarStar,
     /// Single forward Slash
     Slash,
+    /// Two forward Slashes, floor division
+    SlashSlash,
+    /// Percent sign, modulo
+    Percent,
+    /// At sign, matrix multiplication and decorators
+    At,
+
+    // ---- Bitwise Tokens ----
+    /// Ampersand `&`
+    Amp,
+    /// Vertical bar `|`
+    Pipe,
+    /// Caret `^`
+    Caret,
+    /// Tilde `~`
+    Tilde,
+    /// Left shift `<<`
+    LeftShift,
+    /// Right shift `>>`
+    RightShift,
+
+    // ---- Comparison Tokens ----
+    /// Two equals signs `==`
+    EqualEqual,
+    /// Not equal `!=`
+    NotEqual,
+    /// Less than `<`
+    Less,
+    /// Greater than `>`
+    Greater,
+    /// Less than or equal `<=`
+    LessEqual,
+    /// Greater than or equal `>=`
+    GreaterEqual,
+
+    // ---- Assignment Tokens ----
+    /// Single equals sign `=`
+    Equal,
+    /// `+=`
+    PlusEqual,
+    /// `-=`
+    MinusEqual,
+    /// `*=`
+    StarEqual,
+    /// `**=`
+    StarStarEqual,
+    /// `/=`
+    SlashEqual,
+    /// `//=`
+    SlashSlashEqual,
+    /// `%=`
+    PercentEqual,
+    /// `@=`
+    AtEqual,
+    /// `&=`
+    AmpEqual,
+    /// `|=`
+    PipeEqual,
+    /// `^=`
+    CaretEqual,
+    /// `<<=`
+    LeftShiftEqual,
+    /// `>>=`
+    RightShiftEqual,
+    /// Walrus `:=`
+    ColonEqual,
+
+    // ---- Bracket Tokens ----
+    /// Left Parenthesis `(`
+    LeftParen,
+    /// Right Parenthesis `)`
+    RightParen,
+    /// Left Square Bracket `[`
+    LeftBracket,
+    /// Right Square Bracket `]`
+    RightBracket,
+    /// Left Curly Brace `{`
+    LeftBrace,
+    /// Right Curly Brace `}`
+    RightBrace,
+
+    // ---- Delimiter Tokens ----
+    /// Colon, used to open a block
+    Colon,
+    /// Dot, used for attribute access
+    Dot,
+    /// Comma
+    Comma,
+    /// Semicolon, separates statements on one line
+    Semicolon,
+    /// Arrow `->`, used for return annotations
+    Arrow,
+    /// Three dots `...`
+    Ellipsis,
 
     // Data Tokens
     /// Name token, the value of the name is in the string
     Name(String),
+    /// String literal, the decoded value is in the string
+    Str(String),
+    /// Bytes literal, the decoded value is in the vector
+    Bytes(Vec<u8>),
+    /// Formatted string literal, the undecoded text between the quotes is in the string
+    FString(String),
+    /// Integer literal
+    Int(i64),
+    /// Floating point literal
+    Float(#[cfg_attr(feature = "serde", serde(with = "serde_float"))] f64),
+    /// Imaginary literal such as `2j`, the value of the imaginary part
+    Imaginary(#[cfg_attr(feature = "serde", serde(with = "serde_float"))] f64),
+
+    // ---- Generic Tokens ----
+    /// Any operator, bracket or delimiter, with its spelling in the string.
+    ///
+    /// Only produced when `LexerOptions::generic_operators` is set.
+    Op(String),
+    /// A character that cannot start a token.
+    ///
+    /// Only produced by `lex_all` when `LexerOptions::emit_error_tokens` is set.
+    Error(char),
 
     // ---- Keywords ----
+    /// False keyword
+    False,
+    /// None keyword
+    None,
+    /// True keyword
+    True,
+    /// And keyword
+    And,
+    /// As keyword
+    As,
+    /// Assert keyword
+    Assert,
+    /// Async keyword
+    Async,
+    /// Await keyword
+    Await,
+    /// Break keyword
+    Break,
+    /// Class keyword
+    Class,
+    /// Continue keyword
+    Continue,
+    /// Def keyword
+    Def,
+    /// Del keyword
+    Del,
+    /// Elif keyword
+    Elif,
+    /// Else keyword
+    Else,
+    /// Except keyword
+    Except,
+    /// Finally keyword
+    Finally,
+    /// For keyword
+    For,
+    /// From keyword
+    From,
+    /// Global keyword
+    Global,
     /// If keyword
     If,
+    /// Import keyword
+    Import,
+    /// In keyword
+    In,
+    /// Is keyword
+    Is,
+    /// Lambda keyword
+    Lambda,
+    /// Nonlocal keyword
+    Nonlocal,
+    /// Not keyword
+    Not,
+    /// Or keyword
+    Or,
+    /// Pass keyword
+    Pass,
+    /// Raise keyword
+    Raise,
+    /// Return keyword
+    Return,
+    /// Try keyword
+    Try,
+    /// While keyword
+    While,
+    /// With keyword
+    With,
+    /// Yield keyword
+    Yield,
+
+    // ---- Layout Tokens ----
+    /// The end of a logical line
+    Newline,
+    /// The code has been indented one level
+    Indent,
+    /// The code has been dedented on level
+    Dedent,
+    /// A line break that does not end a logical line, such as a blank line.
+    ///
+    /// Only produced when `LexerOptions::emit_nl_tokens` is set.
+    Nl,
+}
+
+/// The type of a token without the data inside, for grouping and sorting tokens by kind.
+///
+/// Kinds are ordered as they are declared, which is the order of `TokenType`:
+/// arithmetic, bitwise, comparison and assignment operators, brackets,
+/// delimiters, data, generic tokens, keywords and then layout tokens.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TokenKind {
+    // ---- Arithmetic Tokens ----
+    /// Plus Sign
+    Plus,
+    /// Minus Sign
+    Minus,
+    /// Asterisk/Multiply sign
+    Star,
+    /// Two Stars in a row
+    StarStar,
+    /// Single forward Slash
+    Slash,
+    /// Two forward Slashes, floor division
+    SlashSlash,
+    /// Percent sign, modulo
+    Percent,
+    /// At sign, matrix multiplication and decorators
+    At,
+
+    // ---- Bitwise Tokens ----
+    /// Ampersand `&`
+    Amp,
+    /// Vertical bar `|`
+    Pipe,
+    /// Caret `^`
+    Caret,
+    /// Tilde `~`
+    Tilde,
+    /// Left shift `<<`
+    LeftShift,
+    /// Right shift `>>`
+    RightShift,
+
+    // ---- Comparison Tokens ----
+    /// Two equals signs `==`
+    EqualEqual,
+    /// Not equal `!=`
+    NotEqual,
+    /// Less than `<`
+    Less,
+    /// Greater than `>`
+    Greater,
+    /// Less than or equal `<=`
+    LessEqual,
+    /// Greater than or equal `>=`
+    GreaterEqual,
+
+    // ---- Assignment Tokens ----
+    /// Single equals sign `=`
+    Equal,
+    /// `+=`
+    PlusEqual,
+    /// `-=`
+    MinusEqual,
+    /// `*=`
+    StarEqual,
+    /// `**=`
+    StarStarEqual,
+    /// `/=`
+    SlashEqual,
+    /// `//=`
+    SlashSlashEqual,
+    /// `%=`
+    PercentEqual,
+    /// `@=`
+    AtEqual,
+    /// `&=`
+    AmpEqual,
+    /// `|=`
+    PipeEqual,
+    /// `^=`
+    CaretEqual,
+    /// `<<=`
+    LeftShiftEqual,
+    /// `>>=`
+    RightShiftEqual,
+    /// Walrus `:=`
+    ColonEqual,
+
+    // ---- Bracket Tokens ----
+    /// Left Parenthesis `(`
+    LeftParen,
+    /// Right Parenthesis `)`
+    RightParen,
+    /// Left Square Bracket `[`
+    LeftBracket,
+    /// Right Square Bracket `]`
+    RightBracket,
+    /// Left Curly Brace `{`
+    LeftBrace,
+    /// Right Curly Brace `}`
+    RightBrace,
+
+    // ---- Delimiter Tokens ----
+    /// Colon, used to open a block
+    Colon,
+    /// Dot, used for attribute access
+    Dot,
+    /// Comma
+    Comma,
+    /// Semicolon, separates statements on one line
+    Semicolon,
+    /// Arrow `->`, used for return annotations
+    Arrow,
+    /// Three dots `...`
+    Ellipsis,
+
+    // Data Tokens
+    /// Name token
+    Name,
+    /// String literal
+    Str,
+    /// Bytes literal
+    Bytes,
+    /// Formatted string literal
+    FString,
+    /// Integer literal
+    Int,
+    /// Floating point literal
+    Float,
+    /// Imaginary literal such as `2j`
+    Imaginary,
+
+    // ---- Generic Tokens ----
+    /// Any operator, bracket or delimiter, from `LexerOptions::generic_operators`
+    Op,
+    /// A character that cannot start a token
+    Error,
+
+    // ---- Keywords ----
+    /// False keyword
+    False,
+    /// None keyword
+    None,
+    /// True keyword
+    True,
+    /// And keyword
+    And,
+    /// As keyword
+    As,
+    /// Assert keyword
+    Assert,
+    /// Async keyword
+    Async,
+    /// Await keyword
+    Await,
+    /// Break keyword
+    Break,
+    /// Class keyword
+    Class,
+    /// Continue keyword
+    Continue,
+    /// Def keyword
+    Def,
+    /// Del keyword
+    Del,
+    /// Elif keyword
+    Elif,
     /// Else keyword
     Else,
+    /// Except keyword
+    Except,
+    /// Finally keyword
+    Finally,
+    /// For keyword
+    For,
+    /// From keyword
+    From,
+    /// Global keyword
+    Global,
+    /// If keyword
+    If,
+    /// Import keyword
+    Import,
+    /// In keyword
+    In,
+    /// Is keyword
+    Is,
+    /// Lambda keyword
+    Lambda,
+    /// Nonlocal keyword
+    Nonlocal,
+    /// Not keyword
+    Not,
+    /// Or keyword
+    Or,
+    /// Pass keyword
+    Pass,
+    /// Raise keyword
+    Raise,
+    /// Return keyword
+    Return,
+    /// Try keyword
+    Try,
+    /// While keyword
+    While,
+    /// With keyword
+    With,
+    /// Yield keyword
+    Yield,
 
     // ---- Layout Tokens ----
+    /// The end of a logical line
+    Newline,
     /// The code has been indented one level
     Indent,
     /// The code has been dedented on level
     Dedent,
+    /// A line break that does not end a logical line, such as a blank line
+    Nl,
 }
 
-/// An error thrown when lexing fails
-#[derive(Debug)]
-pub enum LexError {
-    UnexpectedToken(char, Location, Location),
+/// A `Name` equals the text of the name, every other token is unequal to any text
+impl PartialEq<str> for TokenType {
+    fn eq(&self, other: &str) -> bool {
+        match self {
+            TokenType::Name(name) => name == other,
+            _ => false,
+        }
+    }
 }
 
-/// Alias for what the lexer will return
-pub type LexResult = Result<Vec<Token>, LexError>;
+impl PartialEq<&str> for TokenType {
+    fn eq(&self, other: &&str) -> bool {
+        self == *other
+    }
+}
 
-/// Lex this string.
+/// Serializes float payloads so they survive formats without infinities.
 ///
-/// ```
-/// extern crate oxy_python;
-/// use oxy_python::parser::lex;
-/// let example = "2+2";
-/// let result = lex(example);
-/// ```
-pub fn lex(string: &str) -> LexResult {
-    use TokenType::*;
-    let mut result: Vec<Token> = Vec::new();
-    let mut chars = string.chars();
-    let mut maybe_c: Option<char> = chars.next();
-    let mut column = 1;
-    let mut line = 1;
+/// A literal such as `1e999` lexes to infinity, which JSON cannot hold, so
+/// values that are not finite are written as the strings `inf`, `-inf` and `nan`.
+#[cfg(feature = "serde")]
+mod serde_float {
+    use serde::de::{self, Deserializer, Visitor};
+    use serde::Serializer;
+    use std::fmt;
 
-    macro_rules! advance {
-        () => {{
-            maybe_c = chars.next();
-        }};
+    pub fn serialize<S: Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+        if value.is_finite() {
+            serializer.serialize_f64(*value)
+        } else if value.is_nan() {
+            serializer.serialize_str("nan")
+        } else if *value > 0.0 {
+            serializer.serialize_str("inf")
+        } else {
+            serializer.serialize_str("-inf")
+        }
     }
 
-    macro_rules! push_tok {
-        ($tok: expr, $span: expr) => {{
-            let start = Location::new(column, line);
-            column += $span;
-            let end = Location::new(column - 1, line);
-            result.push(Token {
-                token_type: $tok,
-                start,
-                end,
-            });
-        }};
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        deserializer.deserialize_any(FloatVisitor)
     }
 
-    loop {
-        match maybe_c {
-            Some(c) => {
-                match c {
-                    '+' => push_tok!(Plus, 1),
-                    '-' => push_tok!(Minus, 1),
-                    '*' => {
-                        advance!();
-                        if let Some(n) = maybe_c {
-                            match n {
-                                '*' => {
-                                    push_tok!(StarStar, 2);
-                                    advance!();
-                                }
-                                _ => push_tok!(Star, 1),
-                            }
-                        } else {
-                            push_tok!(Star, 1)
-                        }
-                        continue;
-                    }
-                    _ if c.is_alphabetic() => {
-                        let s = take_until(maybe_c, &mut chars, |x| x.is_alphabetic());
-                        push_tok!(check_keyword(s), s.len() as u64)
-                    }
-                    ' ' => {}
-                    '/' => push_tok!(Slash, 1),
-                    _ => {
-                        return Err(LexError::UnexpectedToken(
-                            c,
-                            Location::new(column, line),
-                            Location::new(column, line),
-                        ))
-                    }
-                }
-                advance!();
+    struct FloatVisitor;
+
+    impl<'de> Visitor<'de> for FloatVisitor {
+        type Value = f64;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a number, or one of \"inf\", \"-inf\" and \"nan\"")
+        }
+
+        fn visit_f64<E: de::Error>(self, value: f64) -> Result<f64, E> {
+            Ok(value)
+        }
+
+        fn visit_i64<E: de::Error>(self, value: i64) -> Result<f64, E> {
+            Ok(value as f64)
+        }
+
+        fn visit_u64<E: de::Error>(self, value: u64) -> Result<f64, E> {
+            Ok(value as f64)
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<f64, E> {
+            match value {
+                "inf" => Ok(f64::INFINITY),
+                "-inf" => Ok(f64::NEG_INFINITY),
+                "nan" => Ok(f64::NAN),
+                _ => Err(E::invalid_value(de::Unexpected::Str(value), &self)),
             }
-            None => break,
         }
     }
+}
 
-    Ok(result)
+/// The broad category a token belongs to
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum TokenCategory {
+    /// Arithmetic and other operators
+    Operator,
+    /// Opening and closing brackets
+    Bracket,
+    /// Punctuation such as colons
+    Delimiter,
+    /// Names and identifiers
+    Name,
+    /// String and number literals
+    Literal,
+    /// Reserved keywords
+    Keyword,
+    /// Newlines and indentation, which have no text of their own
+    Layout,
+    /// Characters the lexer could not understand
+    Error,
 }
 
-fn check_keyword(s: String) -> TokenType {
-    match s.as_str() {
-        "if" => TokenType::If,
-        "else" => TokenType::Else,
-        _ => TokenType::Name(s),
-    }
+/// The kind of operation an operator performs, for highlighters that color them apart
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum OperatorGroup {
+    /// `+`, `-`, `*`, `/`, `//`, `%`, `**` and `@`
+    Arithmetic,
+    /// `==`, `!=`, `<`, `>`, `<=`, `>=`, `in` and `is`
+    Comparison,
+    /// `&`, `|`, `^`, `~`, `<<` and `>>`
+    Bitwise,
+    /// `and`, `or` and `not`
+    Boolean,
+    /// `=`, `:=` and the augmented assignments such as `+=`
+    Assignment,
 }
 
-fn take_until<P>(mut maybe_c: Option<char>, chars: &mut Chars, mut predicate: P) -> String
-where
-    P: FnMut(&char) -> bool,
-{
-    let mut data = String::new();
+/// The value of a number literal, however it was written
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum NumericValue {
+    /// An integer, written in any base
+    Int(i64),
+    /// A float, with a decimal point or an exponent
+    Float(f64),
+    /// The imaginary part of a complex literal such as `2j`
+    Imaginary(f64),
+}
 
-    loop {
-        match maybe_c {
-            Some(c) => {
-                if predicate(&c) {
-                    data.push(c);
-                    maybe_c = chars.next();
-                } else {
-                    break;
-                }
+impl TokenType {
+    /// The category of this token, used by highlighters and other tools
+    pub fn category(&self) -> TokenCategory {
+        use TokenType::*;
+        match self {
+            Plus | Minus | Star | StarStar | Slash | SlashSlash | Percent | At | Amp | Pipe
+            | Caret | Tilde | LeftShift | RightShift | EqualEqual | NotEqual | Less | Greater
+            | LessEqual | GreaterEqual | Equal | PlusEqual | MinusEqual | StarEqual
+            | StarStarEqual | SlashEqual | SlashSlashEqual | PercentEqual | AtEqual | AmpEqual
+            | PipeEqual | CaretEqual | LeftShiftEqual | RightShiftEqual | ColonEqual => {
+                TokenCategory::Operator
+            }
+            LeftParen | RightParen | LeftBracket | RightBracket | LeftBrace | RightBrace => {
+                TokenCategory::Bracket
+            }
+            Colon | Dot | Comma | Semicolon | Arrow | Ellipsis => TokenCategory::Delimiter,
+            Op(_) => TokenCategory::Operator,
+            Error(_) => TokenCategory::Error,
+            Name(_) => TokenCategory::Name,
+            Str(_) | Bytes(_) | FString(_) | Int(_) | Float(_) | Imaginary(_) => {
+                TokenCategory::Literal
             }
-            None => break,
+            False | None | True | And | As | Assert | Async | Await | Break | Class | Continue
+            | Def | Del | Elif | Else | Except | Finally | For | From | Global | If | Import
+            | In | Is | Lambda | Nonlocal | Not | Or | Pass | Raise | Return | Try | While
+            | With | Yield => TokenCategory::Keyword,
+            Newline | Indent | Dedent | Nl => TokenCategory::Layout,
         }
     }
 
-    data
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    macro_rules! print_diff {
-        ($s: expr, $expected: expr, $actual: expr) => {{
-            println!("Error! Expected did not equal Actual for: '{}'", $s);
-            println!("Excepted: {:?}", $expected);
-            println!(
-                "Actual:   {:?}",
-                $actual
-                    .into_iter()
-                    .map(|x| x.token_type.clone())
-                    .collect::<Vec<TokenType>>()
-            );
-            panic!();
-        }};
+    /// The group of this operator, or `None` for tokens that are not operators.
+    ///
+    /// The keyword operators are grouped too. `Op` tokens from
+    /// `LexerOptions::generic_operators` have no group.
+    pub fn operator_group(&self) -> Option<OperatorGroup> {
+        use TokenType::*;
+        let group = match self {
+            Plus | Minus | Star | StarStar | Slash | SlashSlash | Percent | At => {
+                OperatorGroup::Arithmetic
+            }
+            EqualEqual | NotEqual | Less | Greater | LessEqual | GreaterEqual | In | Is => {
+                OperatorGroup::Comparison
+            }
+            Amp | Pipe | Caret | Tilde | LeftShift | RightShift => OperatorGroup::Bitwise,
+            And | Or | Not => OperatorGroup::Boolean,
+            Equal | PlusEqual | MinusEqual | StarEqual | StarStarEqual | SlashEqual
+            | SlashSlashEqual | PercentEqual | AtEqual | AmpEqual | PipeEqual | CaretEqual
+            | LeftShiftEqual | RightShiftEqual | ColonEqual => OperatorGroup::Assignment,
+            LeftParen | RightParen | LeftBracket | RightBracket | LeftBrace | RightBrace
+            | Colon | Dot | Comma | Semicolon | Arrow | Ellipsis | Name(_) | Str(_) | Bytes(_)
+            | FString(_) | Int(_) | Float(_) | Imaginary(_) | Op(_) | Error(_) | False | None
+            | True | As | Assert | Async | Await | Break | Class | Continue | Def | Del | Elif
+            | Else | Except | Finally | For | From | Global | If | Import | Lambda | Nonlocal
+            | Pass | Raise | Return | Try | While | With | Yield | Newline | Indent | Dedent
+            | Nl => return Option::None,
+        };
+        Some(group)
     }
 
-    macro_rules! lex_test {
-        ($s: expr, $( $x:expr ),*) => {{
-            let mut temp_vec = Vec::new();
-            $(
-                temp_vec.push($x);
-            )*
+    /// The value of a number token, so `1_000`, `1000` and `0x3e8` compare equal.
+    ///
+    /// Integers and floats are kept apart, so `1000.0` is not equal to `1000`.
+    pub fn numeric_value(&self) -> Option<NumericValue> {
+        match *self {
+            TokenType::Int(value) => Some(NumericValue::Int(value)),
+            TokenType::Float(value) => Some(NumericValue::Float(value)),
+            TokenType::Imaginary(value) => Some(NumericValue::Imaginary(value)),
+            _ => Option::None,
+        }
+    }
 
-            match lex($s) {
-            Ok(result) =>  {
-                if result.len() != temp_vec.len() {
-                    print_diff!($s, &temp_vec, &result)
-                } else {
-                    for (index, value) in result.iter().enumerate() {
-                        if !value.is_type(&temp_vec[index]) {
-                            print_diff!($s, &temp_vec, &result)
-                        }
-                    }
-                }
-            } Err(e) => {
-                panic!("Did not lex correctly. {}. Received: {:?}", $s, e);
-            }
+    /// The kind of the token, without its data
+    pub fn kind(&self) -> TokenKind {
+        use TokenType::*;
+        match self {
+            Plus => TokenKind::Plus,
+            Minus => TokenKind::Minus,
+            Star => TokenKind::Star,
+            StarStar => TokenKind::StarStar,
+            Slash => TokenKind::Slash,
+            SlashSlash => TokenKind::SlashSlash,
+            Percent => TokenKind::Percent,
+            At => TokenKind::At,
+            Amp => TokenKind::Amp,
+            Pipe => TokenKind::Pipe,
+            Caret => TokenKind::Caret,
+            Tilde => TokenKind::Tilde,
+            LeftShift => TokenKind::LeftShift,
+            RightShift => TokenKind::RightShift,
+            EqualEqual => TokenKind::EqualEqual,
+            NotEqual => TokenKind::NotEqual,
+            Less => TokenKind::Less,
+            Greater => TokenKind::Greater,
+            LessEqual => TokenKind::LessEqual,
+            GreaterEqual => TokenKind::GreaterEqual,
+            Equal => TokenKind::Equal,
+            PlusEqual => TokenKind::PlusEqual,
+            MinusEqual => TokenKind::MinusEqual,
+            StarEqual => TokenKind::StarEqual,
+            StarStarEqual => TokenKind::StarStarEqual,
+            SlashEqual => TokenKind::SlashEqual,
+            SlashSlashEqual => TokenKind::SlashSlashEqual,
+            PercentEqual => TokenKind::PercentEqual,
+            AtEqual => TokenKind::AtEqual,
+            AmpEqual => TokenKind::AmpEqual,
+            PipeEqual => TokenKind::PipeEqual,
+            CaretEqual => TokenKind::CaretEqual,
+            LeftShiftEqual => TokenKind::LeftShiftEqual,
+            RightShiftEqual => TokenKind::RightShiftEqual,
+            ColonEqual => TokenKind::ColonEqual,
+            LeftParen => TokenKind::LeftParen,
+            RightParen => TokenKind::RightParen,
+            LeftBracket => TokenKind::LeftBracket,
+            RightBracket => TokenKind::RightBracket,
+            LeftBrace => TokenKind::LeftBrace,
+            RightBrace => TokenKind::RightBrace,
+            Colon => TokenKind::Colon,
+            Dot => TokenKind::Dot,
+            Comma => TokenKind::Comma,
+            Semicolon => TokenKind::Semicolon,
+            Arrow => TokenKind::Arrow,
+            Ellipsis => TokenKind::Ellipsis,
+            Name(_) => TokenKind::Name,
+            Str(_) => TokenKind::Str,
+            Bytes(_) => TokenKind::Bytes,
+            FString(_) => TokenKind::FString,
+            Int(_) => TokenKind::Int,
+            Float(_) => TokenKind::Float,
+            Imaginary(_) => TokenKind::Imaginary,
+            Op(_) => TokenKind::Op,
+            Error(_) => TokenKind::Error,
+            False => TokenKind::False,
+            None => TokenKind::None,
+            True => TokenKind::True,
+            And => TokenKind::And,
+            As => TokenKind::As,
+            Assert => TokenKind::Assert,
+            Async => TokenKind::Async,
+            Await => TokenKind::Await,
+            Break => TokenKind::Break,
+            Class => TokenKind::Class,
+            Continue => TokenKind::Continue,
+            Def => TokenKind::Def,
+            Del => TokenKind::Del,
+            Elif => TokenKind::Elif,
+            Else => TokenKind::Else,
+            Except => TokenKind::Except,
+            Finally => TokenKind::Finally,
+            For => TokenKind::For,
+            From => TokenKind::From,
+            Global => TokenKind::Global,
+            If => TokenKind::If,
+            Import => TokenKind::Import,
+            In => TokenKind::In,
+            Is => TokenKind::Is,
+            Lambda => TokenKind::Lambda,
+            Nonlocal => TokenKind::Nonlocal,
+            Not => TokenKind::Not,
+            Or => TokenKind::Or,
+            Pass => TokenKind::Pass,
+            Raise => TokenKind::Raise,
+            Return => TokenKind::Return,
+            Try => TokenKind::Try,
+            While => TokenKind::While,
+            With => TokenKind::With,
+            Yield => TokenKind::Yield,
+            Newline => TokenKind::Newline,
+            Indent => TokenKind::Indent,
+            Dedent => TokenKind::Dedent,
+            Nl => TokenKind::Nl,
+        }
     }
-        }};
+
+    /// A stable name for the kind of token, such as `"Plus"` or `"Name"`.
+    ///
+    /// Unlike `Debug`, this never includes the payload, and will not change if
+    /// the derived formatting does, so it is safe for logs and serialized output.
+    pub fn kind_name(&self) -> &'static str {
+        use TokenType::*;
+        match self {
+            Plus => "Plus",
+            Minus => "Minus",
+            Star => "Star",
+            StarStar => "StarStar",
+            Slash => "Slash",
+            SlashSlash => "SlashSlash",
+            Percent => "Percent",
+            At => "At",
+            Amp => "Amp",
+            Pipe => "Pipe",
+            Caret => "Caret",
+            Tilde => "Tilde",
+            LeftShift => "LeftShift",
+            RightShift => "RightShift",
+            EqualEqual => "EqualEqual",
+            NotEqual => "NotEqual",
+            Less => "Less",
+            Greater => "Greater",
+            LessEqual => "LessEqual",
+            GreaterEqual => "GreaterEqual",
+            Equal => "Equal",
+            PlusEqual => "PlusEqual",
+            MinusEqual => "MinusEqual",
+            StarEqual => "StarEqual",
+            StarStarEqual => "StarStarEqual",
+            SlashEqual => "SlashEqual",
+            SlashSlashEqual => "SlashSlashEqual",
+            PercentEqual => "PercentEqual",
+            AtEqual => "AtEqual",
+            AmpEqual => "AmpEqual",
+            PipeEqual => "PipeEqual",
+            CaretEqual => "CaretEqual",
+            LeftShiftEqual => "LeftShiftEqual",
+            RightShiftEqual => "RightShiftEqual",
+            ColonEqual => "ColonEqual",
+            LeftParen => "LeftParen",
+            RightParen => "RightParen",
+            LeftBracket => "LeftBracket",
+            RightBracket => "RightBracket",
+            LeftBrace => "LeftBrace",
+            RightBrace => "RightBrace",
+            Colon => "Colon",
+            Dot => "Dot",
+            Comma => "Comma",
+            Semicolon => "Semicolon",
+            Arrow => "Arrow",
+            Ellipsis => "Ellipsis",
+            Name(_) => "Name",
+            Str(_) => "Str",
+            Bytes(_) => "Bytes",
+            FString(_) => "FString",
+            Int(_) => "Int",
+            Float(_) => "Float",
+            Imaginary(_) => "Imaginary",
+            Op(_) => "Op",
+            Error(_) => "Error",
+            False => "False",
+            None => "None",
+            True => "True",
+            And => "And",
+            As => "As",
+            Assert => "Assert",
+            Async => "Async",
+            Await => "Await",
+            Break => "Break",
+            Class => "Class",
+            Continue => "Continue",
+            Def => "Def",
+            Del => "Del",
+            Elif => "Elif",
+            Else => "Else",
+            Except => "Except",
+            Finally => "Finally",
+            For => "For",
+            From => "From",
+            Global => "Global",
+            If => "If",
+            Import => "Import",
+            In => "In",
+            Is => "Is",
+            Lambda => "Lambda",
+            Nonlocal => "Nonlocal",
+            Not => "Not",
+            Or => "Or",
+            Pass => "Pass",
+            Raise => "Raise",
+            Return => "Return",
+            Try => "Try",
+            While => "While",
+            With => "With",
+            Yield => "Yield",
+            Newline => "Newline",
+            Indent => "Indent",
+            Dedent => "Dedent",
+            Nl => "Nl",
+        }
     }
 
-    #[test]
-    fn test_lex_1_token() {
-        use super::TokenType::*;
-        lex_test!("**", StarStar);
-        lex_test!("+", Plus);
-        lex_test!("-", Minus);
-        lex_test!("*", Star);
-        lex_test!("/", Slash);
-        lex_test!("if", If);
-        lex_test!("else", Else);
+    /// The text of a token that is always spelled the same way, such as `**` or `def`.
+    ///
+    /// Returns `None` for names, literals, layout tokens and `Op`.
+    pub fn text(&self) -> Option<&'static str> {
+        use TokenType::*;
+        let text = match self {
+            Plus => "+",
+            Minus => "-",
+            Star => "*",
+            StarStar => "**",
+            Slash => "/",
+            SlashSlash => "//",
+            Percent => "%",
+            At => "@",
+            Amp => "&",
+            Pipe => "|",
+            Caret => "^",
+            Tilde => "~",
+            LeftShift => "<<",
+            RightShift => ">>",
+            EqualEqual => "==",
+            NotEqual => "!=",
+            Less => "<",
+            Greater => ">",
+            LessEqual => "<=",
+            GreaterEqual => ">=",
+            Equal => "=",
+            PlusEqual => "+=",
+            MinusEqual => "-=",
+            StarEqual => "*=",
+            StarStarEqual => "**=",
+            SlashEqual => "/=",
+            SlashSlashEqual => "//=",
+            PercentEqual => "%=",
+            AtEqual => "@=",
+            AmpEqual => "&=",
+            PipeEqual => "|=",
+            CaretEqual => "^=",
+            LeftShiftEqual => "<<=",
+            RightShiftEqual => ">>=",
+            ColonEqual => ":=",
+            LeftParen => "(",
+            RightParen => ")",
+            LeftBracket => "[",
+            RightBracket => "]",
+            LeftBrace => "{",
+            RightBrace => "}",
+            Colon => ":",
+            Dot => ".",
+            Comma => ",",
+            Semicolon => ";",
+            Arrow => "->",
+            Ellipsis => "...",
+            False => "False",
+            None => "None",
+            True => "True",
+            And => "and",
+            As => "as",
+            Assert => "assert",
+            Async => "async",
+            Await => "await",
+            Break => "break",
+            Class => "class",
+            Continue => "continue",
+            Def => "def",
+            Del => "del",
+            Elif => "elif",
+            Else => "else",
+            Except => "except",
+            Finally => "finally",
+            For => "for",
+            From => "from",
+            Global => "global",
+            If => "if",
+            Import => "import",
+            In => "in",
+            Is => "is",
+            Lambda => "lambda",
+            Nonlocal => "nonlocal",
+            Not => "not",
+            Or => "or",
+            Pass => "pass",
+            Raise => "raise",
+            Return => "return",
+            Try => "try",
+            While => "while",
+            With => "with",
+            Yield => "yield",
+            Name(_) | Str(_) | Bytes(_) | FString(_) | Int(_) | Float(_) | Imaginary(_) | Op(_)
+            | Error(_) | Newline | Indent | Dedent | Nl => return Option::None,
+        };
+        Some(text)
     }
 
-    #[test]
-    fn test_name_token() {
-        use super::TokenType::*;
-        lex_test!("test", Name("test".to_owned()));
-        lex_test!(
-            "test tested",
-            Name("test".to_owned()),
-            Name("tested".to_owned())
-        );
+    /// Asserts if the tokens are the same kind of token, ignoring any data inside.
+    ///
+    /// `Name("a")` matches `Name("b")`, unlike the derived `PartialEq`.
+    pub fn matches_ignoring_payload(&self, other: &TokenType) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
     }
+}
 
-    #[test]
-    fn test_lex_multi_token() {
-        use super::TokenType::*;
-        lex_test!("* *", Star, Star);
-        lex_test!("+ *", Plus, Star);
-        lex_test!("*-", Star, Minus);
+/// An error thrown when lexing fails
+#[derive(Debug)]
+pub enum LexError {
+    UnexpectedToken(char, Location, Location),
+    /// A dedent did not match any outer indentation level
+    BadDedent {
+        /// The width of the indentation that was found
+        found: u64,
+        /// The widths of the indentation levels that were open
+        valid: Vec<u64>,
+        at: Location,
+    },
+    /// A string was not closed before the end of the line or file
+    UnterminatedString(Location),
+    /// A bytes literal holds a character that is not ASCII, which Python rejects
+    NonAsciiBytes(Location),
+    /// An integer literal does not fit in 64 bits
+    IntegerOverflow(Location),
+    /// A number literal is not written correctly, such as `0xZZ`
+    InvalidNumber(Location),
+    /// A tab follows a space in the indentation of a line, which Python rejects
+    /// as the width of the tab is ambiguous
+    TabError {
+        at: Location,
+    },
+    /// The letters before a string are not a prefix Python allows, such as `bf`
+    InvalidStringPrefix {
+        prefix: String,
+        at: Location,
+    },
+    /// A number was written with the Python 2 `L` suffix, which should be dropped
+    Python2LongLiteral {
+        at: Location,
+    },
+    /// `lex_all` stopped after collecting this many errors
+    TooManyErrors {
+        limit: usize,
+    },
+    /// The source has more tokens than `LexerOptions::max_tokens` allows
+    TooManyTokens {
+        limit: usize,
+    },
+    /// The source is longer than `LexerOptions::max_source_bytes` allows
+    SourceTooLarge {
+        limit: usize,
+    },
+    /// A file could not be read, from `lex_files`
+    Io(io::Error),
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LexError::UnexpectedToken(c, at, _) => {
+                write!(f, "unexpected character {:?} at {}", c, at)
+            }
+            LexError::BadDedent { found, valid, at } => {
+                let valid: Vec<String> = valid.iter().map(u64::to_string).collect();
+                write!(
+                    f,
+                    "unindent to {} does not match any outer indentation level at {}; \
+                     valid levels are {}",
+                    found,
+                    at,
+                    valid.join(", ")
+                )
+            }
+            LexError::UnterminatedString(at) => write!(f, "unterminated string starting at {}", at),
+            LexError::NonAsciiBytes(at) => {
+                write!(f, "bytes literal at {} can only hold ASCII characters", at)
+            }
+            LexError::IntegerOverflow(at) => {
+                write!(f, "integer at {} does not fit in 64 bits", at)
+            }
+            LexError::InvalidNumber(at) => write!(f, "invalid number at {}", at),
+            LexError::TabError { at } => {
+                write!(f, "tab after spaces in the indentation at {}", at)
+            }
+            LexError::InvalidStringPrefix { prefix, at } => {
+                write!(f, "invalid string prefix {:?} at {}", prefix, at)
+            }
+            LexError::Python2LongLiteral { at } => {
+                write!(f, "the L suffix at {} is from Python 2, remove it", at)
+            }
+            LexError::TooManyErrors { limit } => {
+                write!(f, "stopped after {} errors", limit)
+            }
+            LexError::TooManyTokens { limit } => {
+                write!(f, "the source has more than {} tokens", limit)
+            }
+            LexError::SourceTooLarge { limit } => {
+                write!(f, "the source is longer than {} bytes", limit)
+            }
+            LexError::Io(error) => write!(f, "could not read the file: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for LexError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LexError::Io(error) => Some(error),
+            _ => Option::None,
+        }
+    }
+}
+
+/// A lex error along with the file it came from, for tools that lex many files
+#[derive(Debug)]
+pub struct SourcedError {
+    /// The path of the file, as the caller named it
+    pub file: String,
+    /// What went wrong in the file
+    pub error: LexError,
+}
+
+impl fmt::Display for SourcedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.file, self.error)
+    }
+}
+
+impl std::error::Error for SourcedError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Alias for what the lexer will return
+pub type LexResult = Result<Vec<Token>, LexError>;
+
+/// A set of characters given by a predicate, for `LexerOptions::identifier_start`
+/// and `LexerOptions::identifier_continue`.
+///
+/// The predicate is shared, so cloning the options does not copy it.
+#[derive(Clone)]
+pub struct CharClass(Arc<dyn Fn(char) -> bool + Send + Sync>);
+
+impl CharClass {
+    /// Make a class of the characters the predicate accepts
+    pub fn new<F: Fn(char) -> bool + Send + Sync + 'static>(predicate: F) -> CharClass {
+        CharClass(Arc::new(predicate))
+    }
+
+    /// Checks if the character is in the class
+    pub fn contains(&self, c: char) -> bool {
+        (self.0)(c)
+    }
+}
+
+impl fmt::Debug for CharClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("CharClass(..)")
+    }
+}
+
+/// Options that change how the lexer behaves.
+///
+/// The default options lex standard python.
+#[derive(Debug, Clone)]
+pub struct LexerOptions {
+    /// Lowercase the text of every name, for a case insensitive dialect.
+    /// Keywords are still matched as written, and string contents are not changed.
+    pub fold_identifier_case: bool,
+    /// The number of errors `lex_all` collects before it gives up
+    pub max_errors: usize,
+    /// Produce `TokenType::Op` for every operator, bracket and delimiter,
+    /// like the `OP` token of CPython's `tokenize` module
+    pub generic_operators: bool,
+    /// Replace the standard keywords with this mapping from spelling to token,
+    /// for dialects with renamed keywords. Names not in the map are `Name` tokens.
+    pub keyword_map: Option<HashMap<String, TokenType>>,
+    /// Have `lex_all` produce a `TokenType::Error` for each unexpected character,
+    /// so the tokens still cover all of the source
+    pub emit_error_tokens: bool,
+    /// Produce a `TokenType::Nl` for each line break that does not end a logical
+    /// line, so blank lines, comment lines and lines inside brackets are kept
+    pub emit_nl_tokens: bool,
+    /// Fill in `Location::byte_column` on every token, at the cost of a pass over the tokens
+    pub byte_columns: bool,
+    /// Fill in `Token::byte_range` on every token, at the cost of a pass over the tokens
+    pub byte_ranges: bool,
+    /// Check names against the keywords. When off, every word is a `Name`,
+    /// for generic tokenizing or dialects without Python's keywords.
+    pub recognize_keywords: bool,
+    /// Stop with `LexError::TooManyTokens` once the source has more tokens than this,
+    /// to bound the memory used on hostile input
+    pub max_tokens: Option<usize>,
+    /// Stop with `LexError::SourceTooLarge` before lexing a source longer than this
+    /// many bytes, a cheap guard for services that lex untrusted input
+    pub max_source_bytes: Option<usize>,
+    /// Number lines and columns from 0 instead of 1, as the language server
+    /// protocol does. Error locations are shifted too.
+    pub zero_based_positions: bool,
+    /// The characters that may start a name, in place of Python's `XID_Start` and `_`.
+    /// Operators, brackets and quotes are matched before this is checked.
+    pub identifier_start: Option<CharClass>,
+    /// The characters that may follow the first in a name, in place of Python's
+    /// `XID_Continue`
+    pub identifier_continue: Option<CharClass>,
+}
+
+impl Default for LexerOptions {
+    fn default() -> LexerOptions {
+        LexerOptions {
+            fold_identifier_case: false,
+            max_errors: 100,
+            generic_operators: false,
+            keyword_map: None,
+            emit_error_tokens: false,
+            emit_nl_tokens: false,
+            byte_columns: false,
+            byte_ranges: false,
+            recognize_keywords: true,
+            max_tokens: None,
+            max_source_bytes: None,
+            zero_based_positions: false,
+            identifier_start: None,
+            identifier_continue: None,
+        }
+    }
+}
+
+/// Lex this string.
+///
+/// ```
+/// extern crate oxy_python;
+/// use oxy_python::parser::lex;
+/// let example = "2+2";
+/// let result = lex(example);
+/// ```
+pub fn lex(string: &str) -> LexResult {
+    lex_with_options(string, &LexerOptions::default())
+}
+
+/// Lex this string with the given options.
+///
+/// ```
+/// extern crate oxy_python;
+/// use oxy_python::parser::{lex_with_options, LexerOptions};
+/// let options = LexerOptions {
+///     fold_identifier_case: true,
+///     ..LexerOptions::default()
+/// };
+/// let result = lex_with_options("Foo", &options);
+/// ```
+pub fn lex_with_options(string: &str, options: &LexerOptions) -> LexResult {
+    let mut tokens = Vec::new();
+    if let Err(error) = lex_lines(string, options, LexMode::default(), &mut tokens) {
+        return Err(finish_error(error, options));
+    }
+    finish_tokens(string, options, &mut tokens);
+    Ok(tokens)
+}
+
+/// Lex this string with the given options, calling `on_token` with each token as it is found.
+///
+/// The callback sees every token before lexing finishes or fails, for side
+/// effects such as progress reports on large files. It sees the locations as
+/// first found, before `byte_columns`, `byte_ranges` and `zero_based_positions`
+/// are applied to the returned tokens.
+///
+/// ```
+/// extern crate oxy_python;
+/// use oxy_python::parser::{lex_with_callback, LexerOptions};
+/// let mut seen = 0;
+/// let tokens = lex_with_callback("a + b", &LexerOptions::default(), &mut |_| seen += 1).unwrap();
+/// assert_eq!(seen, tokens.len());
+/// ```
+pub fn lex_with_callback(
+    string: &str,
+    options: &LexerOptions,
+    on_token: &mut dyn FnMut(&Token),
+) -> LexResult {
+    let mode = LexMode {
+        on_token: Some(on_token),
+        ..LexMode::default()
+    };
+    let mut tokens = Vec::new();
+    if let Err(error) = lex_lines(string, options, mode, &mut tokens) {
+        return Err(finish_error(error, options));
+    }
+    finish_tokens(string, options, &mut tokens);
+    Ok(tokens)
+}
+
+/// Lex the source of a file, naming the file in any error.
+///
+/// The caller reads the file, so `file` can be any path or label.
+///
+/// ```
+/// extern crate oxy_python;
+/// use oxy_python::parser::lex_file;
+/// let error = lex_file("src/main.py", "a $").unwrap_err();
+/// assert_eq!(error.file, "src/main.py");
+/// ```
+pub fn lex_file(file: &str, source: &str) -> Result<Vec<Token>, SourcedError> {
+    lex_file_with_options(file, source, &LexerOptions::default())
+}
+
+/// Lex the source of a file with the given options, naming the file in any error.
+///
+/// ```
+/// extern crate oxy_python;
+/// use oxy_python::parser::{lex_file_with_options, LexError, LexerOptions};
+/// let options = LexerOptions {
+///     max_source_bytes: Some(4),
+///     ..LexerOptions::default()
+/// };
+/// let error = lex_file_with_options("big.py", "a = 12345", &options).unwrap_err();
+/// assert!(matches!(error.error, LexError::SourceTooLarge { limit: 4 }));
+/// ```
+pub fn lex_file_with_options(
+    file: &str,
+    source: &str,
+    options: &LexerOptions,
+) -> Result<Vec<Token>, SourcedError> {
+    lex_with_options(source, options).map_err(|error| SourcedError {
+        file: file.to_owned(),
+        error,
+    })
+}
+
+/// Read and lex each file, keeping the tokens of each file with its path.
+///
+/// Files are lexed independently, so indentation and brackets do not carry
+/// from one file to the next. The first file that cannot be read or lexed
+/// stops the whole run, and the error names that file.
+///
+/// ```no_run
+/// extern crate oxy_python;
+/// use oxy_python::parser::lex_files;
+/// use std::path::PathBuf;
+/// let files = lex_files(&[PathBuf::from("a.py"), PathBuf::from("b.py")]).unwrap();
+/// for (path, tokens) in files {
+///     println!("{}: {} tokens", path.display(), tokens.len());
+/// }
+/// ```
+pub fn lex_files(paths: &[PathBuf]) -> Result<Vec<(PathBuf, Vec<Token>)>, SourcedError> {
+    let mut files = Vec::with_capacity(paths.len());
+    for path in paths {
+        let file = path.display().to_string();
+        let source = fs::read_to_string(path).map_err(|error| SourcedError {
+            file: file.clone(),
+            error: LexError::Io(error),
+        })?;
+        let tokens = lex_file(&file, &source)?;
+        files.push((path.clone(), tokens));
+    }
+    Ok(files)
+}
+
+/// Count the tokens in this string without storing them.
+///
+/// This runs the same scanner as `lex`, so the count always matches the
+/// length of its tokens, but the tokens are dropped as soon as they are found
+/// and names and strings are scanned without copying out their values.
+///
+/// ```
+/// extern crate oxy_python;
+/// use oxy_python::parser::count_tokens;
+/// assert_eq!(count_tokens("2 + 2").unwrap(), 3);
+/// ```
+pub fn count_tokens(string: &str) -> Result<usize, LexError> {
+    let mut counter = TokenCounter::default();
+    lex_lines(
+        string,
+        &LexerOptions::default(),
+        LexMode::default(),
+        &mut counter,
+    )?;
+    Ok(counter.count)
+}
+
+/// Lex this string, collecting every error instead of stopping at the first.
+///
+/// Lexing continues past an error by skipping the bad input, so the tokens are
+/// a best guess. A dedent that matches no open block opens a new block at that
+/// indentation, keeping `Indent` and `Dedent` balanced. When an error is found
+/// after `options.max_errors` have been collected, lexing stops there, the
+/// open blocks are closed, and a final `LexError::TooManyErrors` is added in
+/// its place.
+///
+/// ```
+/// extern crate oxy_python;
+/// use oxy_python::parser::{lex_all, LexerOptions};
+/// let (tokens, errors) = lex_all("a $ b", &LexerOptions::default());
+/// assert_eq!(tokens.len(), 2);
+/// assert_eq!(errors.len(), 1);
+/// ```
+pub fn lex_all(string: &str, options: &LexerOptions) -> (Vec<Token>, Vec<LexError>) {
+    let mut errors = Vec::new();
+    let mode = LexMode {
+        errors: Some(&mut errors),
+        ..LexMode::default()
+    };
+    let mut tokens = Vec::new();
+    let result = lex_lines(string, options, mode, &mut tokens);
+    if let Err(error) = result {
+        errors.push(error);
+        tokens.clear();
+    }
+    finish_tokens(string, options, &mut tokens);
+    let errors = errors
+        .into_iter()
+        .map(|error| finish_error(error, options))
+        .collect();
+    (tokens, errors)
+}
+
+/// Lex only the lines of the source that overlap the byte range.
+///
+/// The range is widened to whole physical lines, so a range starting or ending
+/// in the middle of a token still lexes that token. Locations are relative to
+/// the full source. `Indent` and `Dedent` tokens are not produced, as the
+/// indentation of the lines before the range is not known, and a range that
+/// starts inside a multi-line string will not lex correctly.
+///
+/// ```
+/// extern crate oxy_python;
+/// use oxy_python::parser::lex_range;
+/// let tokens = lex_range("a\nb + c\nd", 4..5).unwrap();
+/// assert_eq!(tokens[0].start().line(), 2);
+/// ```
+pub fn lex_range(source: &str, range: Range<usize>) -> LexResult {
+    let start = range.start.min(source.len());
+    let end = range.end.min(source.len()).max(start);
+    let start = source.as_bytes()[..start]
+        .iter()
+        .rposition(|b| *b == b'\n')
+        .map_or(0, |index| index + 1);
+    let end = source.as_bytes()[end..]
+        .iter()
+        .position(|b| *b == b'\n')
+        .map_or(source.len(), |index| end + index + 1);
+    let first_line = source.as_bytes()[..start]
+        .iter()
+        .filter(|b| **b == b'\n')
+        .count() as u64
+        + 1;
+
+    let mode = LexMode {
+        first_line,
+        layout: false,
+        ..LexMode::default()
+    };
+    let mut tokens = Vec::new();
+    lex_lines(
+        &source[start..end],
+        &LexerOptions::default(),
+        mode,
+        &mut tokens,
+    )?;
+    Ok(tokens)
+}
+
+/// Apply the options that rewrite token locations once lexing is done
+fn finish_tokens(string: &str, options: &LexerOptions, tokens: &mut [Token]) {
+    let source_map = needs_source_map(options).then(|| SourceMap::new(string));
+    finish_tokens_with(source_map.as_ref(), options, tokens);
+}
+
+/// Checks if finishing tokens with these options needs a map of the source
+fn needs_source_map(options: &LexerOptions) -> bool {
+    options.byte_columns || options.byte_ranges
+}
+
+/// Apply the options that rewrite token locations, given the map of the source
+/// when `needs_source_map` asks for one
+fn finish_tokens_with(
+    source_map: Option<&SourceMap>,
+    options: &LexerOptions,
+    tokens: &mut [Token],
+) {
+    if let Some(source_map) = source_map {
+        if options.byte_columns {
+            fill_byte_columns(source_map, tokens);
+        }
+        if options.byte_ranges {
+            fill_byte_ranges(source_map, tokens);
+        }
+    }
+    if options.zero_based_positions {
+        for token in tokens {
+            token.start.shift_to_zero_based();
+            token.end.shift_to_zero_based();
+        }
+    }
+}
+
+/// Apply the options that rewrite locations to an error
+fn finish_error(mut error: LexError, options: &LexerOptions) -> LexError {
+    if options.zero_based_positions {
+        match &mut error {
+            LexError::UnexpectedToken(_, start, end) => {
+                start.shift_to_zero_based();
+                end.shift_to_zero_based();
+            }
+            LexError::BadDedent { at, .. }
+            | LexError::TabError { at }
+            | LexError::InvalidStringPrefix { at, .. }
+            | LexError::Python2LongLiteral { at }
+            | LexError::UnterminatedString(at)
+            | LexError::NonAsciiBytes(at)
+            | LexError::IntegerOverflow(at)
+            | LexError::InvalidNumber(at) => at.shift_to_zero_based(),
+            LexError::TooManyErrors { .. }
+            | LexError::TooManyTokens { .. }
+            | LexError::SourceTooLarge { .. }
+            | LexError::Io(_) => {}
+        }
+    }
+    error
+}
+
+/// Lex only the first `lines` physical lines of the source, for previews.
+///
+/// Lexing stops at the first line break after those lines, so the rest of the
+/// source is never scanned. A string that starts within the lines is kept
+/// whole, along with the rest of the line it ends on. Blocks still open are
+/// closed with `Dedent` tokens, as at the end of a file, but brackets still
+/// open are left open.
+///
+/// ```
+/// extern crate oxy_python;
+/// use oxy_python::parser::lex_first_lines;
+/// let tokens = lex_first_lines("a\nb\nc $\n", 2).unwrap();
+/// assert_eq!(tokens.len(), 4);
+/// ```
+pub fn lex_first_lines(source: &str, lines: u64) -> LexResult {
+    if lines == 0 {
+        return Ok(Vec::new());
+    }
+    let mode = LexMode {
+        last_line: Some(lines),
+        ..LexMode::default()
+    };
+    let mut tokens = Vec::new();
+    lex_lines(source, &LexerOptions::default(), mode, &mut tokens)?;
+    Ok(tokens)
+}
+
+/// Lex the string one logical line at a time, as an iterator of tokens.
+///
+/// The tokens are the same as those from `lex`, but only one logical line is
+/// held at once, so a caller can stop early without lexing the rest of the
+/// source. Tokens before an error are still produced, then the error, then
+/// nothing more.
+///
+/// ```
+/// extern crate oxy_python;
+/// use oxy_python::parser::{lex_iter, TokenType};
+/// let first = lex_iter("a\nb $").next().unwrap().unwrap();
+/// assert!(first.is_type(&TokenType::Name("a".to_owned())));
+/// ```
+pub fn lex_iter(string: &str) -> Lexer<'_> {
+    Lexer::new(string)
+}
+
+/// A lexer that produces tokens as they are found, one logical line at a time.
+///
+/// ```
+/// extern crate oxy_python;
+/// use oxy_python::parser::{Lexer, LexerOptions};
+/// let options = LexerOptions {
+///     zero_based_positions: true,
+///     ..LexerOptions::default()
+/// };
+/// let mut lexer = Lexer::with_options("a + b", &options);
+/// assert_eq!(lexer.next().unwrap().unwrap().start().column(), 0);
+/// ```
+pub struct Lexer<'s> {
+    source: &'s str,
+    state: LexState,
+    options: LexerOptions,
+    /// The map of the source, when the options need one to finish tokens
+    source_map: Option<SourceMap<'s>>,
+    /// The tokens of the last logical line that have not been taken yet
+    line: LineBuffer,
+    /// An error to give once the tokens before it have been taken
+    error: Option<LexError>,
+}
+
+impl<'s> Lexer<'s> {
+    /// Lex the string with the default options
+    pub fn new(string: &'s str) -> Lexer<'s> {
+        Lexer::with_options(string, &LexerOptions::default())
+    }
+
+    /// Lex the string with the given options, which are copied.
+    ///
+    /// The options apply as they do for `lex_with_options`.
+    pub fn with_options(string: &'s str, options: &LexerOptions) -> Lexer<'s> {
+        let mut lexer = Lexer {
+            source: string,
+            state: LexState::new(1),
+            options: options.clone(),
+            source_map: needs_source_map(options).then(|| SourceMap::new(string)),
+            line: LineBuffer::default(),
+            error: None,
+        };
+        if let Err(error) = check_source_size(string, options) {
+            lexer.state.finished = true;
+            lexer.error = Some(error);
+        }
+        lexer
+    }
+}
+
+impl<'s> Iterator for Lexer<'s> {
+    type Item = Result<Token, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(token) = self.line.tokens.pop_front() {
+                return Some(Ok(token));
+            }
+            if let Some(error) = self.error.take() {
+                return Some(Err(error));
+            }
+            if self.state.finished {
+                return None;
+            }
+            let result = resume_lexing(
+                self.source,
+                &mut self.state,
+                &self.options,
+                LexMode::default(),
+                &mut self.line,
+            );
+            finish_tokens_with(
+                self.source_map.as_ref(),
+                &self.options,
+                self.line.tokens.make_contiguous(),
+            );
+            if let Err(error) = result {
+                self.state.finished = true;
+                self.error = Some(finish_error(error, &self.options));
+            }
+        }
+    }
+
+    /// Every token takes at least one byte except `Indent` and `Dedent`. An
+    /// `Indent` needs whitespace that no token takes, and each `Dedent` closes an
+    /// `Indent` or a block already open, so twice the bytes left plus the open
+    /// blocks bounds what is still to come.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let ready = self.line.tokens.len() + usize::from(self.error.is_some());
+        if self.state.finished {
+            return (ready, Some(ready));
+        }
+        let left = self.source.len() - self.state.offset;
+        let open = self.state.indents.len() - 1;
+        let upper = left
+            .checked_mul(2)
+            .and_then(|tokens| tokens.checked_add(ready + open + 1));
+        (ready, upper)
+    }
+}
+
+/// Lex source read from `reader`, as an iterator of tokens.
+///
+/// The source is read in chunks and each logical line is lexed once it has
+/// been read in full, so only the unfinished line is kept in memory. The
+/// tokens are the same as those from `lex` on the whole source. Source that is
+/// not valid UTF-8, or a failed read, gives a `LexError::Io`.
+///
+/// Reading carries on past an error until the end of the input, or until
+/// the line lexes, because more input can finish a string or number that
+/// looked broken.
+///
+/// ```
+/// extern crate oxy_python;
+/// use oxy_python::parser::lex_reader;
+/// use std::io::Cursor;
+/// let tokens: Vec<_> = lex_reader(Cursor::new("a = 1\n")).collect::<Result<_, _>>().unwrap();
+/// assert_eq!(tokens.len(), 4);
+/// ```
+pub fn lex_reader<R: io::Read>(reader: R) -> impl Iterator<Item = Result<Token, LexError>> {
+    ReaderLexer {
+        reader,
+        pending: String::new(),
+        partial: Vec::new(),
+        at_eof: false,
+        state: LexState::new(1),
+        options: LexerOptions::default(),
+        line: LineBuffer::default(),
+        error: None,
+    }
+}
+
+/// The number of bytes `lex_reader` asks for on each read
+const READ_CHUNK: usize = 8 * 1024;
+
+/// A lexer over a reader, made by `lex_reader`
+struct ReaderLexer<R> {
+    reader: R,
+    /// Source that has been read, from the start of the line being lexed
+    /// or an earlier one
+    pending: String,
+    /// Bytes at the end of the last read that do not make a whole character yet
+    partial: Vec<u8>,
+    at_eof: bool,
+    /// Where lexing is, with offsets into `pending`
+    state: LexState,
+    options: LexerOptions,
+    /// The tokens of the last logical line that have not been taken yet
+    line: LineBuffer,
+    /// An error to give once the tokens before it have been taken
+    error: Option<LexError>,
+}
+
+impl<R: io::Read> ReaderLexer<R> {
+    /// Read more source into `pending`, at least a line break and as much as is
+    /// already waiting, so a long line is rescanned only a few times. A line break
+    /// is all an interactive reader needs to send to have its line lexed.
+    fn read_more(&mut self) -> Result<(), LexError> {
+        let wanted = self.pending.len() - self.state.offset;
+        let mut bytes = std::mem::take(&mut self.partial);
+        let start = bytes.len();
+        let mut line_break = false;
+        loop {
+            let filled = bytes.len();
+            bytes.resize(filled + READ_CHUNK, 0);
+            let read = match self.reader.read(&mut bytes[filled..]) {
+                Ok(read) => read,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {
+                    bytes.truncate(filled);
+                    continue;
+                }
+                Err(error) => return Err(LexError::Io(error)),
+            };
+            bytes.truncate(filled + read);
+            if read == 0 {
+                self.at_eof = true;
+                break;
+            }
+            line_break |= bytes[filled..].contains(&b'\n');
+            if line_break && bytes.len() - start >= wanted {
+                break;
+            }
+        }
+        // Drop what has been lexed before adding to it
+        self.pending.drain(..self.state.offset);
+        self.state.offset = 0;
+        match std::str::from_utf8(&bytes) {
+            Ok(text) => self.pending.push_str(text),
+            // A character cut off by the end of the read waits for the next read
+            Err(error) if error.error_len().is_none() && !self.at_eof => {
+                let valid = error.valid_up_to();
+                self.pending
+                    .push_str(std::str::from_utf8(&bytes[..valid]).unwrap());
+                self.partial = bytes[valid..].to_vec();
+            }
+            Err(error) => {
+                return Err(LexError::Io(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    error,
+                )))
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: io::Read> Iterator for ReaderLexer<R> {
+    type Item = Result<Token, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(token) = self.line.tokens.pop_front() {
+                return Some(Ok(token));
+            }
+            if let Some(error) = self.error.take() {
+                return Some(Err(error));
+            }
+            if self.state.finished {
+                return None;
+            }
+
+            // Lex the next logical line, keeping nothing until it is known to be whole
+            let mut state = self.state.clone();
+            let mut line = LineBuffer::default();
+            let result = resume_lexing(
+                &self.pending,
+                &mut state,
+                &self.options,
+                LexMode::default(),
+                &mut line,
+            );
+            let whole = result.is_ok() && !state.finished;
+            if whole || self.at_eof {
+                self.state = state;
+                self.line = line;
+                if let Err(error) = result {
+                    self.state.finished = true;
+                    self.error = Some(error);
+                }
+                continue;
+            }
+
+            if let Err(error) = self.read_more() {
+                self.state.finished = true;
+                return Some(Err(error));
+            }
+        }
+    }
+}
+
+/// Set the byte column of every token location from the map of the source it was lexed from
+fn fill_byte_columns(source_map: &SourceMap, tokens: &mut [Token]) {
+    let byte_column = |location: &Location| {
+        let line_start = source_map.byte_offset(&Location::new(location.line, 1));
+        (source_map.byte_offset(location) - line_start) as u64 + 1
+    };
+    for token in tokens {
+        token.start.byte_column = Some(byte_column(&token.start));
+        token.end.byte_column = Some(byte_column(&token.end));
+    }
+}
+
+/// Set the byte range of every token from the map of the source it was lexed from
+fn fill_byte_ranges(source_map: &SourceMap, tokens: &mut [Token]) {
+    for token in tokens {
+        let range = match token.token_type {
+            TokenType::Indent | TokenType::Dedent => {
+                let start = source_map.byte_offset(&token.start);
+                start..start
+            }
+            _ => source_map.span_to_byte_range(&token.span()),
+        };
+        token.byte_range = Some(range);
+    }
+}
+
+/// How `lex_lines` should run
+struct LexMode<'e> {
+    /// The number of the first line of the string
+    first_line: u64,
+    /// Produce `Indent` and `Dedent` tokens
+    layout: bool,
+    /// Collect errors here and keep lexing, instead of stopping at the first one
+    errors: Option<&'e mut Vec<LexError>>,
+    /// Stop at the first line break that ends this line or a later one
+    last_line: Option<u64>,
+    /// Called with each token as it is produced
+    on_token: Option<&'e mut dyn FnMut(&Token)>,
+}
+
+impl<'e> Default for LexMode<'e> {
+    fn default() -> LexMode<'e> {
+        LexMode {
+            first_line: 1,
+            layout: true,
+            errors: None,
+            last_line: None,
+            on_token: None,
+        }
+    }
+}
+
+/// Where `lex_lines` puts the tokens it finds
+trait TokenSink {
+    /// Add the next token
+    fn push(&mut self, token: Token);
+
+    /// Checks if a newline at this point would end a non-empty logical line
+    fn ends_logical_line(&self) -> bool;
+
+    /// Checks if lexing should stop before the next logical line, to be resumed later
+    fn pause(&self) -> bool {
+        false
+    }
+
+    /// Checks if tokens need their names and string values, sinks that only
+    /// look at the kinds of tokens are given empty ones
+    fn keeps_values(&self) -> bool {
+        true
+    }
+}
+
+impl TokenSink for Vec<Token> {
+    fn push(&mut self, token: Token) {
+        Vec::push(self, token);
+    }
+
+    fn ends_logical_line(&self) -> bool {
+        match self
+            .iter()
+            .rev()
+            .find(|token| !token.is_type(&TokenType::Nl))
+        {
+            Some(token) => !token.is_type(&TokenType::Newline),
+            None => false,
+        }
+    }
+}
+
+/// Counts tokens for `count_tokens`, remembering only enough to place newlines
+#[derive(Default)]
+struct TokenCounter {
+    count: usize,
+    /// A newline at this point would end a non-empty logical line
+    ends_line: bool,
+}
+
+impl TokenSink for TokenCounter {
+    fn push(&mut self, token: Token) {
+        self.count += 1;
+        if !token.is_type(&TokenType::Nl) {
+            self.ends_line = !token.is_type(&TokenType::Newline);
+        }
+    }
+
+    fn ends_logical_line(&self) -> bool {
+        self.ends_line
+    }
+
+    fn keeps_values(&self) -> bool {
+        false
+    }
+}
+
+/// Holds one logical line at a time for `Lexer`
+#[derive(Default)]
+struct LineBuffer {
+    tokens: VecDeque<Token>,
+    /// A newline at this point would end a non-empty logical line
+    ends_line: bool,
+}
+
+impl TokenSink for LineBuffer {
+    fn push(&mut self, token: Token) {
+        if !token.is_type(&TokenType::Nl) {
+            self.ends_line = !token.is_type(&TokenType::Newline);
+        }
+        self.tokens.push_back(token);
+    }
+
+    fn ends_logical_line(&self) -> bool {
+        self.ends_line
+    }
+
+    fn pause(&self) -> bool {
+        !self.tokens.is_empty()
+    }
+}
+
+/// Lex the string in the given mode, pushing each token into `result`
+fn lex_lines<S: TokenSink>(
+    string: &str,
+    options: &LexerOptions,
+    mode: LexMode,
+    result: &mut S,
+) -> Result<(), LexError> {
+    check_source_size(string, options)?;
+    let mut state = LexState::new(mode.first_line);
+    resume_lexing(string, &mut state, options, mode, result)
+}
+
+/// Stop before lexing a source longer than `LexerOptions::max_source_bytes`
+fn check_source_size(string: &str, options: &LexerOptions) -> Result<(), LexError> {
+    match options.max_source_bytes {
+        Some(limit) if string.len() > limit => Err(LexError::SourceTooLarge { limit }),
+        _ => Ok(()),
+    }
+}
+
+/// The byte order mark some editors put at the start of a UTF-8 file
+pub(crate) const BOM: char = '\u{feff}';
+
+/// Where `lex_lines` is in the source, kept between calls when the sink pauses
+#[derive(Clone)]
+struct LexState {
+    /// The byte offset of the next character
+    offset: usize,
+    /// Where the next character is
+    cursor: Location,
+    /// How many brackets are open. Newlines inside brackets do not end the logical line.
+    depth: usize,
+    /// The widths of the open indentation levels, the outermost level is always 0
+    indents: Vec<u64>,
+    at_line_start: bool,
+    /// The number of tokens produced so far
+    emitted: usize,
+    /// The whole source has been lexed
+    finished: bool,
+}
+
+impl LexState {
+    fn new(first_line: u64) -> LexState {
+        LexState {
+            offset: 0,
+            cursor: Location::new(first_line, 1),
+            depth: 0,
+            indents: vec![0],
+            at_line_start: true,
+            emitted: 0,
+            finished: false,
+        }
+    }
+}
+
+/// Carry on lexing from the state until the end of the source, or until the
+/// sink asks for a pause at the start of a logical line
+fn resume_lexing<S: TokenSink>(
+    string: &str,
+    state: &mut LexState,
+    options: &LexerOptions,
+    mut mode: LexMode,
+    result: &mut S,
+) -> Result<(), LexError> {
+    use TokenType::*;
+    // Work on locals, which are written back if the sink pauses
+    let mut scanner = Scanner::new(string, state.offset);
+    let mut cursor = state.cursor;
+    let mut depth = state.depth;
+    let mut indents = std::mem::take(&mut state.indents);
+    let mut at_line_start = state.at_line_start;
+    let mut emitted = state.emitted;
+    let keep_values = result.keeps_values();
+
+    // A byte order mark at the very start is skipped, as CPython's tokenize
+    // does, and takes no column
+    if cursor.line == mode.first_line && cursor.column == 1 && scanner.peek() == Some(BOM) {
+        scanner.bump();
+    }
+
+    macro_rules! advance {
+        () => {{
+            scanner.bump();
+        }};
+    }
+
+    // Stop at the error, or record it and carry on when collecting errors
+    macro_rules! error {
+        ($err: expr) => {{
+            match mode.errors {
+                // One error past the limit stops lexing, closing the open blocks
+                Some(ref mut errors) if errors.len() >= options.max_errors => {
+                    errors.push(LexError::TooManyErrors {
+                        limit: options.max_errors,
+                    });
+                    finish!();
+                }
+                Some(ref mut errors) => errors.push($err),
+                Option::None => return Err($err),
+            }
+        }};
+    }
+
+    // Add a finished token, stopping if there are too many
+    macro_rules! emit {
+        ($token: expr) => {{
+            if let Some(limit) = options.max_tokens {
+                if emitted >= limit {
+                    return Err(LexError::TooManyTokens { limit });
+                }
+            }
+            emitted += 1;
+            let token = $token;
+            if let Some(ref mut on_token) = mode.on_token {
+                on_token(&token);
+            }
+            result.push(token);
+        }};
+    }
+
+    macro_rules! push_tok {
+        ($tok: expr, $span: expr) => {{
+            let start = cursor;
+            cursor.advance_column($span);
+            let end = cursor.previous_column();
+            let mut token_type = $tok;
+            if options.generic_operators {
+                token_type = generic_operator(token_type);
+            }
+            emit!(Token {
+                token_type,
+                start,
+                end,
+                byte_range: Option::None,
+                string_kind: Option::None,
+            });
+        }};
+    }
+
+    // Report a character that cannot start a token and step over it
+    macro_rules! unexpected {
+        ($c: expr) => {{
+            error!(LexError::UnexpectedToken($c, cursor, cursor));
+            // Only reached when collecting errors
+            if options.emit_error_tokens {
+                push_tok!(Error($c), 1);
+            } else {
+                cursor.advance_column(1);
+            }
+        }};
+    }
+
+    macro_rules! push_layout {
+        ($tok: expr) => {{
+            emit!(Token {
+                token_type: $tok,
+                start: cursor,
+                end: cursor,
+                byte_range: Option::None,
+                string_kind: Option::None,
+            });
+        }};
+    }
+
+    // Close any blocks still open at the end of the file, and stop
+    macro_rules! finish {
+        () => {{
+            while indents.len() > 1 {
+                indents.pop();
+                push_layout!(Dedent);
+            }
+            state.offset = scanner.offset;
+            state.finished = true;
+            return Ok(());
+        }};
+    }
+
+    macro_rules! open_bracket {
+        ($tok: expr) => {{
+            depth += 1;
+            push_tok!($tok, 1)
+        }};
+    }
+
+    macro_rules! close_bracket {
+        ($tok: expr) => {{
+            if depth > 0 {
+                depth -= 1;
+            }
+            push_tok!($tok, 1)
+        }};
+    }
+
+    // Lex an operator that may be followed by `=`, such as `+` and `+=`
+    macro_rules! with_equal {
+        ($tok: expr, $equal: expr) => {{
+            advance!();
+            if let Some('=') = scanner.peek() {
+                push_tok!($equal, 2);
+                advance!();
+            } else {
+                push_tok!($tok, 1);
+            }
+            continue;
+        }};
+    }
+
+    // Lex an operator that may be doubled and followed by `=`, such as `*`, `**` and `**=`
+    macro_rules! doubled_with_equal {
+        ($c: expr, $tok: expr, $equal: expr, $double: expr, $double_equal: expr) => {{
+            advance!();
+            match scanner.peek() {
+                Some(n) if n == $c => {
+                    advance!();
+                    if let Some('=') = scanner.peek() {
+                        push_tok!($double_equal, 3);
+                        advance!();
+                    } else {
+                        push_tok!($double, 2);
+                    }
+                }
+                Some('=') => {
+                    push_tok!($equal, 2);
+                    advance!();
+                }
+                _ => push_tok!($tok, 1),
+            }
+            continue;
+        }};
+    }
+
+    // Lex a string starting at its opening quote
+    macro_rules! push_string {
+        ($start: expr, $prefix: expr) => {{
+            let start = $start;
+            let prefix: StringPrefix = $prefix;
+            let raw = prefix.raw || prefix.format;
+            let quote = scanner.peek().unwrap();
+            let kind = StringKind {
+                quote,
+                triple: scanner.peek_nth(1) == Some(quote) && scanner.peek_nth(2) == Some(quote),
+                prefix,
+            };
+            let begin = scanner.offset;
+            let value = if keep_values {
+                take_string::<String>(&mut scanner, &mut cursor, raw, prefix.bytes)
+            } else {
+                take_string::<Discard>(&mut scanner, &mut cursor, raw, prefix.bytes)
+                    .map(|_| String::new())
+            };
+            match value {
+                // Escapes such as `\xff` are fine, but the text itself must be ASCII
+                Some(_) if prefix.bytes && !scanner.since(begin).is_ascii() => {
+                    error!(LexError::NonAsciiBytes(start))
+                }
+                Some(value) => emit!(Token {
+                    token_type: prefix.token(value),
+                    start,
+                    end: cursor.previous_column(),
+                    byte_range: Option::None,
+                    string_kind: Some(kind),
+                }),
+                Option::None => error!(LexError::UnterminatedString(start)),
+            }
+            continue;
+        }};
+    }
+
+    while let Some(c) = scanner.peek() {
+        if at_line_start {
+            if result.pause() {
+                *state = LexState {
+                    offset: scanner.offset,
+                    cursor,
+                    depth,
+                    indents,
+                    at_line_start,
+                    emitted,
+                    finished: false,
+                };
+                return Ok(());
+            }
+            at_line_start = false;
+            let indentation = take_indentation(&mut scanner);
+            let indent_start = cursor;
+            cursor.advance_column(indentation.chars);
+            let next = scanner.peek();
+            let blank = matches!(next, Option::None | Some('\n') | Some('\r') | Some('#'));
+            if let Some(tab) = indentation.tab_after_space {
+                if !blank {
+                    let mut at = indent_start;
+                    at.advance_column(tab);
+                    error!(LexError::TabError { at });
+                }
+            }
+
+            match next {
+                // Blank and comment only lines do not change the indentation
+                _ if blank => {}
+                Some(_) if mode.layout => {
+                    let width = indentation.width;
+                    if width > *indents.last().unwrap() {
+                        indents.push(width);
+                        push_layout!(Indent);
+                    } else {
+                        // The outermost level is 0, so some level is never wider
+                        let kept = indents.iter().rposition(|level| *level <= width).unwrap() + 1;
+                        // The levels are only copied for the error
+                        let bad = (indents[kept - 1] != width).then(|| indents.clone());
+                        while indents.len() > kept {
+                            indents.pop();
+                            push_layout!(Dedent);
+                        }
+                        if let Some(valid) = bad {
+                            error!(LexError::BadDedent {
+                                found: width,
+                                valid,
+                                at: cursor,
+                            });
+                            // When collecting errors, take the line's indentation as a
+                            // new level so the rest of the block still lexes
+                            indents.push(width);
+                            push_layout!(Indent);
+                        }
+                    }
+                }
+                _ => {}
+            }
+            continue;
+        }
+
+        match c {
+            '+' => with_equal!(Plus, PlusEqual),
+            '-' => {
+                advance!();
+                match scanner.peek() {
+                    Some('=') => {
+                        push_tok!(MinusEqual, 2);
+                        advance!();
+                    }
+                    Some('>') => {
+                        push_tok!(Arrow, 2);
+                        advance!();
+                    }
+                    _ => push_tok!(Minus, 1),
+                }
+                continue;
+            }
+            '*' => doubled_with_equal!('*', Star, StarEqual, StarStar, StarStarEqual),
+            '/' => doubled_with_equal!('/', Slash, SlashEqual, SlashSlash, SlashSlashEqual),
+            '<' => doubled_with_equal!('<', Less, LessEqual, LeftShift, LeftShiftEqual),
+            '>' => doubled_with_equal!('>', Greater, GreaterEqual, RightShift, RightShiftEqual),
+            '%' => with_equal!(Percent, PercentEqual),
+            '@' => with_equal!(At, AtEqual),
+            '&' => with_equal!(Amp, AmpEqual),
+            '|' => with_equal!(Pipe, PipeEqual),
+            '^' => with_equal!(Caret, CaretEqual),
+            '=' => with_equal!(Equal, EqualEqual),
+            ':' => with_equal!(Colon, ColonEqual),
+            '~' => push_tok!(Tilde, 1),
+            '!' if scanner.peek_next() == Some('=') => {
+                push_tok!(NotEqual, 2);
+                advance!();
+            }
+            _ if is_identifier_start(c, options) => {
+                // Slice the name out of the source, only names that are not keywords allocate
+                let mut span = 0;
+                let mut first = true;
+                let s = scanner.eat_while(|x| {
+                    // The first character passed the start check already
+                    let in_name = first || is_identifier_continue(x, options);
+                    first = false;
+                    if in_name {
+                        span += 1;
+                    }
+                    in_name
+                });
+                if let Some('\'') | Some('"') = scanner.peek() {
+                    if let Some(prefix) = StringPrefix::parse(s) {
+                        let start = cursor;
+                        cursor.advance_column(span);
+                        push_string!(start, prefix);
+                    } else if StringPrefix::is_prefix_like(s) {
+                        let start = cursor;
+                        error!(LexError::InvalidStringPrefix {
+                            prefix: s.to_owned(),
+                            at: start,
+                        });
+                        // Keep going with whatever the letters asked for
+                        cursor.advance_column(span);
+                        push_string!(start, StringPrefix::lenient(s));
+                    }
+                }
+                if !keep_values {
+                    push_tok!(Name(String::new()), span);
+                    continue;
+                }
+                let mut token_type = if options.recognize_keywords {
+                    check_keyword(s, options.keyword_map.as_ref())
+                } else {
+                    Name(s.to_owned())
+                };
+                // Keywords are matched as written, only the names are folded
+                if options.fold_identifier_case {
+                    if let Name(name) = &mut token_type {
+                        *name = name.to_lowercase();
+                    }
+                }
+                push_tok!(token_type, span);
+                continue;
+            }
+            _ if c.is_ascii_digit()
+                || (c == '.' && matches!(scanner.peek_next(), Some('0'..='9'))) =>
+            {
+                let at = cursor;
+                let text = take_number(&mut scanner);
+                // Numbers are ASCII, so the bytes are the columns
+                let span = text.len() as u64;
+                let suffix = matches!(scanner.peek(), Some('L') | Some('l'));
+                if suffix {
+                    let mut at = cursor;
+                    at.advance_column(span);
+                    error!(LexError::Python2LongLiteral { at });
+                    // Drop the suffix and keep the number
+                    advance!();
+                }
+                match parse_number(text, at) {
+                    Ok(token) => push_tok!(token, span),
+                    Err(err) => {
+                        error!(err);
+                        cursor.advance_column(span);
+                    }
+                }
+                if suffix {
+                    cursor.advance_column(1);
+                }
+                continue;
+            }
+            '\'' | '"' => push_string!(cursor, StringPrefix::default()),
+            '#' => {
+                let comment = scanner.eat_while(|x| x != '\n');
+                cursor.advance_column(comment.chars().count() as u64);
+                continue;
+            }
+            '(' => open_bracket!(LeftParen),
+            ')' => close_bracket!(RightParen),
+            '[' => open_bracket!(LeftBracket),
+            ']' => close_bracket!(RightBracket),
+            '{' => open_bracket!(LeftBrace),
+            '}' => close_bracket!(RightBrace),
+            '.' => {
+                if scanner.rest().starts_with("...") {
+                    push_tok!(Ellipsis, 3);
+                    advance!();
+                    advance!();
+                } else {
+                    push_tok!(Dot, 1);
+                }
+            }
+            ',' => push_tok!(Comma, 1),
+            ';' => push_tok!(Semicolon, 1),
+            '\\' => {
+                let rest = &scanner.rest()[1..];
+                if rest.starts_with('\n') || rest.starts_with("\r\n") {
+                    // An escaped newline joins the next line onto this one
+                    advance!();
+                    if let Some('\r') = scanner.peek() {
+                        advance!();
+                    }
+                    cursor.advance_line();
+                } else {
+                    unexpected!(c);
+                }
+            }
+            '\n' => {
+                // Blank lines and lines inside brackets do not end a logical line
+                if depth == 0 && result.ends_logical_line() {
+                    push_tok!(Newline, 1);
+                } else if options.emit_nl_tokens {
+                    push_tok!(Nl, 1);
+                }
+                if depth == 0 {
+                    at_line_start = true;
+                }
+                cursor.advance_line();
+                if mode.last_line.is_some_and(|last| cursor.line > last) {
+                    break;
+                }
+            }
+            ' ' | '\t' | '\r' | '\x0c' => cursor.advance_column(1),
+            _ => {
+                unexpected!(c);
+            }
+        }
+        advance!();
+    }
+
+    finish!();
+}
+
+/// The leading whitespace of a line
+struct Indentation {
+    /// The width of the whitespace, with tabs rounded up to the next multiple of 8
+    width: u64,
+    /// The number of characters the whitespace took up
+    chars: u64,
+    /// How many characters in the first tab that came after a space is
+    tab_after_space: Option<u64>,
+}
+
+/// Consumes the whitespace at the start of a line
+fn take_indentation(scanner: &mut Scanner) -> Indentation {
+    let mut indentation = Indentation {
+        width: 0,
+        chars: 0,
+        tab_after_space: None,
+    };
+    let mut seen_space = false;
+
+    while let Some(c) = scanner.peek() {
+        match c {
+            ' ' => {
+                indentation.width += 1;
+                seen_space = true;
+            }
+            '\t' => {
+                indentation.width += 8 - indentation.width % 8;
+                if seen_space && indentation.tab_after_space.is_none() {
+                    indentation.tab_after_space = Some(indentation.chars);
+                }
+            }
+            // A form feed resets the indentation, as in CPython
+            '\x0c' => {
+                indentation.width = 0;
+                seen_space = false;
+            }
+            _ => break,
+        }
+        indentation.chars += 1;
+        scanner.bump();
+    }
+
+    indentation
+}
+
+/// The letters written before the opening quote of a string
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StringPrefix {
+    /// `r`, backslashes are not escapes
+    pub raw: bool,
+    /// `b`, the literal is bytes
+    pub bytes: bool,
+    /// `f`, the literal is a formatted string
+    pub format: bool,
+}
+
+/// How a string literal was written, for tools that must write it back the same way
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StringKind {
+    /// The quote character, `'` or `"`
+    pub quote: char,
+    /// The literal is fenced by three quotes
+    pub triple: bool,
+    /// The letters before the opening quote
+    pub prefix: StringPrefix,
+}
+
+impl StringPrefix {
+    /// Parse the prefix, returning `None` if it is not a valid string prefix
+    ///
+    /// Letters may be in any case and `r` may come before or after `b` or `f`.
+    fn parse(text: &str) -> Option<StringPrefix> {
+        let mut prefix = StringPrefix::default();
+        if text.len() > 2 {
+            return Option::None;
+        }
+        match text.to_ascii_lowercase().as_str() {
+            "u" => {}
+            "r" => prefix.raw = true,
+            "b" => prefix.bytes = true,
+            "f" => prefix.format = true,
+            "rb" | "br" => {
+                prefix.raw = true;
+                prefix.bytes = true;
+            }
+            "rf" | "fr" => {
+                prefix.raw = true;
+                prefix.format = true;
+            }
+            _ => return Option::None,
+        }
+        Some(prefix)
+    }
+
+    /// Whether the text is made only of prefix letters, so a quote after it was
+    /// meant to start a string
+    fn is_prefix_like(text: &str) -> bool {
+        !text.is_empty() && text.chars().all(|c| "rRbBuUfF".contains(c))
+    }
+
+    /// Every flag any of the letters ask for, used to carry on after an invalid prefix
+    fn lenient(text: &str) -> StringPrefix {
+        let mut prefix = StringPrefix::default();
+        for c in text.chars() {
+            match c.to_ascii_lowercase() {
+                'r' => prefix.raw = true,
+                'b' => prefix.bytes = true,
+                'f' => prefix.format = true,
+                _ => {}
+            }
+        }
+        prefix
+    }
+
+    /// The token for a string with this prefix
+    fn token(&self, value: String) -> TokenType {
+        if self.bytes {
+            let mut bytes = Vec::with_capacity(value.len());
+            for c in value.chars() {
+                // Escapes such as `\xff` decode to a single byte
+                if (c as u32) < 256 {
+                    bytes.push(c as u32 as u8);
+                } else {
+                    bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+            }
+            TokenType::Bytes(bytes)
+        } else if self.format {
+            TokenType::FString(value)
+        } else {
+            TokenType::Str(value)
+        }
+    }
+}
+
+/// Consumes a string literal starting at the opening quote and returns its value.
+///
+/// Escapes are decoded unless `raw` is set, in which case the text between
+/// the quotes is returned as written. `bytes` disables the `\u` and `\U` escapes.
+/// `cursor` is moved past the closing quote.
+/// Returns `None` if the string is not closed.
+fn take_string<V: StringValue>(
+    scanner: &mut Scanner,
+    cursor: &mut Location,
+    raw: bool,
+    bytes: bool,
+) -> Option<V> {
+    let quote = scanner.peek()?;
+    let triple = scanner.peek_nth(1) == Some(quote) && scanner.peek_nth(2) == Some(quote);
+    let mut value = V::default();
+
+    macro_rules! next {
+        () => {{
+            scanner.bump();
+            cursor.advance_column(1);
+        }};
+    }
+
+    next!();
+    if triple {
+        next!();
+        next!();
+    }
+
+    loop {
+        let c = scanner.peek()?;
+        match c {
+            _ if c == quote => {
+                if !triple {
+                    next!();
+                    return Some(value);
+                }
+                if scanner.peek_nth(1) == Some(quote) && scanner.peek_nth(2) == Some(quote) {
+                    next!();
+                    next!();
+                    next!();
+                    return Some(value);
+                }
+                value.push(c);
+                next!();
+            }
+            '\n' => {
+                if !triple {
+                    return Option::None;
+                }
+                value.push(c);
+                scanner.bump();
+                cursor.advance_line();
+            }
+            '\\' if raw => {
+                // The backslash is kept, but still stops the next character closing the string
+                value.push(c);
+                next!();
+                match scanner.peek()? {
+                    '\n' => {
+                        value.push('\n');
+                        scanner.bump();
+                        cursor.advance_line();
+                    }
+                    escaped => {
+                        value.push(escaped);
+                        next!();
+                    }
+                }
+            }
+            '\\' => {
+                next!();
+                let escaped = scanner.peek()?;
+                match escaped {
+                    '\n' => {
+                        // An escaped newline continues the string on the next line
+                        scanner.bump();
+                        cursor.advance_line();
+                        continue;
+                    }
+                    'n' => value.push('\n'),
+                    't' => value.push('\t'),
+                    'r' => value.push('\r'),
+                    'a' => value.push('\x07'),
+                    'b' => value.push('\x08'),
+                    'f' => value.push('\x0c'),
+                    'v' => value.push('\x0b'),
+                    '\\' | '\'' | '"' => value.push(escaped),
+                    '0'..='7' => {
+                        let mut code = escaped.to_digit(8).unwrap();
+                        for _ in 0..2 {
+                            match scanner.peek_next().and_then(|d| d.to_digit(8)) {
+                                Some(digit) => {
+                                    code = code * 8 + digit;
+                                    next!();
+                                }
+                                Option::None => break,
+                            }
+                        }
+                        value.push(std::char::from_u32(code).unwrap());
+                    }
+                    'x' | 'u' | 'U' if escaped == 'x' || !bytes => {
+                        let length = match escaped {
+                            'x' => 2,
+                            'u' => 4,
+                            _ => 8,
+                        };
+                        let digits = scanner.rest()[1..].get(..length);
+                        let decoded = digits.and_then(decode_hex_escape);
+                        match decoded {
+                            Some(decoded) => {
+                                value.push(decoded);
+                                for _ in 0..length {
+                                    next!();
+                                }
+                            }
+                            // Invalid escapes are kept as written
+                            Option::None => {
+                                value.push('\\');
+                                value.push(escaped);
+                            }
+                        }
+                    }
+                    // Unknown escapes are kept as written
+                    _ => {
+                        value.push('\\');
+                        value.push(escaped);
+                    }
+                }
+                next!();
+            }
+            _ => {
+                value.push(c);
+                next!();
+            }
+        }
+    }
+}
+
+/// Decode the digits of a `\x`, `\u` or `\U` escape, which must all be hex digits
+pub(crate) fn decode_hex_escape(digits: &str) -> Option<char> {
+    // `from_str_radix` would also take a sign
+    if !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Option::None;
+    }
+    u32::from_str_radix(digits, 16)
+        .ok()
+        .and_then(std::char::from_u32)
+}
+
+/// Where `take_string` puts the characters of a string's value
+trait StringValue: Default {
+    fn push(&mut self, c: char);
+}
+
+impl StringValue for String {
+    fn push(&mut self, c: char) {
+        String::push(self, c);
+    }
+}
+
+/// Drops the value of a string, for scanning past it without allocating
+#[derive(Default)]
+struct Discard;
+
+impl StringValue for Discard {
+    fn push(&mut self, _: char) {}
+}
+
+/// Checks if the character may start a name
+fn is_identifier_start(c: char, options: &LexerOptions) -> bool {
+    match &options.identifier_start {
+        Some(class) => class.contains(c),
+        Option::None => c == '_' || unicode_ident::is_xid_start(c),
+    }
+}
+
+/// Checks if the character may follow the first in a name
+fn is_identifier_continue(c: char, options: &LexerOptions) -> bool {
+    match &options.identifier_continue {
+        Some(class) => class.contains(c),
+        Option::None => unicode_ident::is_xid_continue(c),
+    }
+}
+
+/// Consumes a number literal and returns it as written.
+///
+/// This covers integers in any base, floats, exponents and the `j` imaginary suffix.
+fn take_number<'s>(scanner: &mut Scanner<'s>) -> &'s str {
+    let start = scanner.offset;
+
+    if scanner.peek() == Some('0') {
+        if let Some('x') | Some('X') | Some('o') | Some('O') | Some('b') | Some('B') =
+            scanner.peek_next()
+        {
+            scanner.bump();
+            scanner.bump();
+            let in_number = |c: char| c.is_ascii_alphanumeric() || c == '_';
+            while let Some(c) = scanner.peek().filter(|c| in_number(*c)) {
+                // A final `l` is a Python 2 long suffix, left for the caller to report
+                if matches!(c, 'l' | 'L') && !scanner.peek_next().is_some_and(in_number) {
+                    break;
+                }
+                scanner.bump();
+            }
+            return scanner.since(start);
+        }
+    }
+
+    let is_digit = |c: char| c.is_ascii_digit() || c == '_';
+    scanner.eat_while(is_digit);
+    if scanner.peek() == Some('.') {
+        scanner.bump();
+        scanner.eat_while(is_digit);
+    }
+    if let Some('e') | Some('E') = scanner.peek() {
+        let mut next = scanner.peek_nth(1);
+        if let Some('+') | Some('-') = next {
+            next = scanner.peek_nth(2);
+        }
+        if let Some('0'..='9') = next {
+            scanner.bump();
+            if let Some('+') | Some('-') = scanner.peek() {
+                scanner.bump();
+            }
+            scanner.eat_while(is_digit);
+        }
+    }
+    if let Some('j') | Some('J') = scanner.peek() {
+        scanner.bump();
+    }
+
+    scanner.since(start)
+}
+
+/// Parse a number written as `text` into its token
+fn parse_number(text: &str, at: Location) -> Result<TokenType, LexError> {
+    // Most numbers are written without separators or capitals and need no copy
+    let text: Cow<str> = if text.bytes().any(|b| b == b'_' || b.is_ascii_uppercase()) {
+        Cow::Owned(text.replace('_', "").to_ascii_lowercase())
+    } else {
+        Cow::Borrowed(text)
+    };
+    let int_error = |e: std::num::ParseIntError| match e.kind() {
+        IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => LexError::IntegerOverflow(at),
+        _ => LexError::InvalidNumber(at),
+    };
+
+    let radix = match text.get(..2) {
+        Some("0x") => 16,
+        Some("0o") => 8,
+        Some("0b") => 2,
+        _ => 10,
+    };
+    if radix != 10 {
+        return i64::from_str_radix(&text[2..], radix)
+            .map(TokenType::Int)
+            .map_err(int_error);
+    }
+    if let Some(imaginary) = text.strip_suffix('j') {
+        return imaginary
+            .parse()
+            .map(TokenType::Imaginary)
+            .map_err(|_| LexError::InvalidNumber(at));
+    }
+    if text.contains(['.', 'e']) {
+        return text
+            .parse()
+            .map(TokenType::Float)
+            .map_err(|_| LexError::InvalidNumber(at));
+    }
+    text.parse().map(TokenType::Int).map_err(int_error)
+}
+
+/// Turns an operator, bracket or delimiter into an `Op` token with its spelling
+fn generic_operator(token_type: TokenType) -> TokenType {
+    match token_type.category() {
+        TokenCategory::Operator | TokenCategory::Bracket | TokenCategory::Delimiter => {
+            match token_type.text() {
+                Some(text) => TokenType::Op(text.to_owned()),
+                None => token_type,
+            }
+        }
+        _ => token_type,
+    }
+}
+
+/// The standard keywords, hashed perfectly at compile time
+static KEYWORDS: phf::Map<&'static str, TokenType> = phf_map! {
+    "False" => TokenType::False,
+    "None" => TokenType::None,
+    "True" => TokenType::True,
+    "and" => TokenType::And,
+    "as" => TokenType::As,
+    "assert" => TokenType::Assert,
+    "async" => TokenType::Async,
+    "await" => TokenType::Await,
+    "break" => TokenType::Break,
+    "class" => TokenType::Class,
+    "continue" => TokenType::Continue,
+    "def" => TokenType::Def,
+    "del" => TokenType::Del,
+    "elif" => TokenType::Elif,
+    "else" => TokenType::Else,
+    "except" => TokenType::Except,
+    "finally" => TokenType::Finally,
+    "for" => TokenType::For,
+    "from" => TokenType::From,
+    "global" => TokenType::Global,
+    "if" => TokenType::If,
+    "import" => TokenType::Import,
+    "in" => TokenType::In,
+    "is" => TokenType::Is,
+    "lambda" => TokenType::Lambda,
+    "nonlocal" => TokenType::Nonlocal,
+    "not" => TokenType::Not,
+    "or" => TokenType::Or,
+    "pass" => TokenType::Pass,
+    "raise" => TokenType::Raise,
+    "return" => TokenType::Return,
+    "try" => TokenType::Try,
+    "while" => TokenType::While,
+    "with" => TokenType::With,
+    "yield" => TokenType::Yield,
+};
+
+/// Find the keyword spelled `s`, using `keyword_map` instead of the standard keywords if given
+fn check_keyword(s: &str, keyword_map: Option<&HashMap<String, TokenType>>) -> TokenType {
+    let keyword = match keyword_map {
+        Some(keyword_map) => keyword_map.get(s),
+        None => KEYWORDS.get(s),
+    };
+    match keyword {
+        Some(keyword) => keyword.clone(),
+        None => TokenType::Name(s.to_owned()),
+    }
+}
+
+/// A byte offset into the source being lexed, which always sits on a character boundary
+struct Scanner<'s> {
+    source: &'s str,
+    /// Where the current character starts
+    offset: usize,
+}
+
+impl<'s> Scanner<'s> {
+    /// A scanner starting at the byte offset, which must be on a character boundary
+    fn new(source: &'s str, offset: usize) -> Scanner<'s> {
+        Scanner { source, offset }
+    }
+
+    /// The source from the current character on
+    fn rest(&self) -> &'s str {
+        &self.source[self.offset..]
+    }
+
+    /// The current character, or `None` at the end of the source
+    #[inline(always)]
+    fn peek(&self) -> Option<char> {
+        match self.source.as_bytes().get(self.offset) {
+            // Most source is ASCII, which needs no decoding
+            Some(byte) if byte.is_ascii() => Some(char::from(*byte)),
+            Some(_) => self.peek_wide(),
+            Option::None => Option::None,
+        }
+    }
+
+    /// `peek` for a character longer than a byte, kept apart so the ASCII path stays small
+    #[cold]
+    #[inline(never)]
+    fn peek_wide(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    /// The character after the current one
+    #[inline(always)]
+    fn peek_next(&self) -> Option<char> {
+        let mut chars = self.rest().chars();
+        chars.next();
+        chars.next()
+    }
+
+    /// The character `n` after the current one
+    fn peek_nth(&self, n: usize) -> Option<char> {
+        self.rest().chars().nth(n)
+    }
+
+    /// Step past the current character
+    #[inline(always)]
+    fn bump(&mut self) {
+        match self.source.as_bytes().get(self.offset) {
+            Some(byte) if byte.is_ascii() => self.offset += 1,
+            Some(_) => self.offset += self.peek_wide().map_or(0, char::len_utf8),
+            Option::None => {}
+        }
+    }
+
+    /// Step past characters while the predicate holds, returning the text stepped over
+    fn eat_while<P>(&mut self, mut predicate: P) -> &'s str
+    where
+        P: FnMut(char) -> bool,
+    {
+        let start = self.offset;
+        while let Some(c) = self.peek() {
+            if !predicate(c) {
+                break;
+            }
+            self.bump();
+        }
+        self.since(start)
+    }
+
+    /// The text from the byte offset `start` up to the current character
+    fn since(&self, start: usize) -> &'s str {
+        &self.source[start..self.offset]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! print_diff {
+        ($s: expr, $expected: expr, $actual: expr) => {{
+            println!("Error! Expected did not equal Actual for: '{}'", $s);
+            println!("Excepted: {:?}", $expected);
+            println!(
+                "Actual:   {:?}",
+                $actual
+                    .into_iter()
+                    .map(|x| x.token_type.clone())
+                    .collect::<Vec<TokenType>>()
+            );
+            panic!();
+        }};
+    }
+
+    macro_rules! lex_test {
+        ($s: expr, $( $x:expr ),*) => {{
+            let mut temp_vec = Vec::new();
+            $(
+                temp_vec.push($x);
+            )*
+
+            match lex($s) {
+            Ok(result) =>  {
+                if result.len() != temp_vec.len() {
+                    print_diff!($s, &temp_vec, &result)
+                } else {
+                    for (index, value) in result.iter().enumerate() {
+                        if !value.is_type(&temp_vec[index]) {
+                            print_diff!($s, &temp_vec, &result)
+                        }
+                    }
+                }
+            } Err(e) => {
+                panic!("Did not lex correctly. {}. Received: {:?}", $s, e);
+            }
+    }
+        }};
+    }
+
+    #[test]
+    fn test_lex_1_token() {
+        use super::TokenType::*;
+        lex_test!("**", StarStar);
+        lex_test!("+", Plus);
+        lex_test!("-", Minus);
+        lex_test!("*", Star);
+        lex_test!("/", Slash);
+        lex_test!("if", If);
+        lex_test!("else", Else);
+    }
+
+    #[test]
+    fn test_name_token() {
+        use super::TokenType::*;
+        lex_test!("test", Name("test".to_owned()));
+        lex_test!(
+            "test tested",
+            Name("test".to_owned()),
+            Name("tested".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_lex_multi_token() {
+        use super::TokenType::*;
+        lex_test!("* *", Star, Star);
+        lex_test!("+ *", Plus, Star);
+        lex_test!("*-", Star, Minus);
+        lex_test!("a+b", Name("a".to_owned()), Plus, Name("b".to_owned()));
+        lex_test!("a.b", Name("a".to_owned()), Dot, Name("b".to_owned()));
+    }
+
+    #[test]
+    fn test_newline_token() {
+        use super::TokenType::*;
+        lex_test!("a\nb", Name("a".to_owned()), Newline, Name("b".to_owned()));
+        lex_test!(
+            "a\n\n\nb",
+            Name("a".to_owned()),
+            Newline,
+            Name("b".to_owned())
+        );
+        lex_test!("\n\na", Name("a".to_owned()));
+        lex_test!(
+            "(a\n+ b)",
+            LeftParen,
+            Name("a".to_owned()),
+            Plus,
+            Name("b".to_owned()),
+            RightParen
+        );
+    }
+
+    #[test]
+    fn test_layout_tokens() {
+        use super::TokenType::*;
+        let a = || Name("a".to_owned());
+        lex_test!(
+            "if a:\n  a\na",
+            If,
+            a(),
+            Colon,
+            Newline,
+            Indent,
+            a(),
+            Newline,
+            Dedent,
+            a()
+        );
+        lex_test!("if a:\n\ta", If, a(), Colon, Newline, Indent, a(), Dedent);
+        lex_test!(
+            "if a:\n  if a:\n    a\n\n  # comment\na",
+            If,
+            a(),
+            Colon,
+            Newline,
+            Indent,
+            If,
+            a(),
+            Colon,
+            Newline,
+            Indent,
+            a(),
+            Newline,
+            Dedent,
+            Dedent,
+            a()
+        );
+        lex_test!("(a\n    a)", LeftParen, a(), a(), RightParen);
+    }
+
+    #[test]
+    fn test_bad_dedent() {
+        match lex("if a:\n    a\n  a") {
+            Err(LexError::BadDedent { at, .. }) => assert_eq!((at.line, at.column), (3, 3)),
+            other => panic!("Expected a bad dedent. Received: {:?}", other),
+        }
+        match lex("if a:\n  if b:\n    a\n   b") {
+            Err(error @ LexError::BadDedent { .. }) => {
+                assert_eq!(
+                    error.to_string(),
+                    "unindent to 3 does not match any outer indentation level at line 4, \
+                     column 4; valid levels are 0, 2, 4"
+                );
+                if let LexError::BadDedent { found, valid, .. } = error {
+                    assert_eq!((found, valid), (3, vec![0, 2, 4]));
+                }
+            }
+            other => panic!("Expected a bad dedent. Received: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_tab_after_space() {
+        use super::TokenType::*;
+        match lex(" \t x") {
+            Err(error @ LexError::TabError { .. }) => {
+                assert_eq!(
+                    error.to_string(),
+                    "tab after spaces in the indentation at line 1, column 2"
+                );
+            }
+            other => panic!("Expected a tab error. Received: {:?}", other),
+        }
+        match lex("if a:\n    \tb") {
+            Err(LexError::TabError { at }) => assert_eq!((at.line, at.column), (2, 5)),
+            other => panic!("Expected a tab error. Received: {:?}", other),
+        }
+        lex_test!("\t x", Indent, Name("x".to_owned()), Dedent);
+        // Blank and comment only lines are not checked
+        lex_test!(
+            "a\n \t\n \t# c\nb",
+            Name("a".to_owned()),
+            Newline,
+            Name("b".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_comment() {
+        use super::TokenType::*;
+        lex_test!("a # comment * +", Name("a".to_owned()));
+        lex_test!(
+            "a # comment\na",
+            Name("a".to_owned()),
+            Newline,
+            Name("a".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_string_token() {
+        use super::TokenType::*;
+        lex_test!("'a'", Str("a".to_owned()));
+        lex_test!("\"b\" 'c'", Str("b".to_owned()), Str("c".to_owned()));
+        lex_test!("''", Str("".to_owned()));
+        lex_test!("'''x\n'y'\n'''", Str("x\n'y'\n".to_owned()));
+        lex_test!(r"'a\nb\'\q'", Str("a\nb'\\q".to_owned()));
+        lex_test!("\"a\\\nb\"", Str("ab".to_owned()));
+    }
+
+    #[test]
+    fn test_string_escapes() {
+        let value = |source| match &lex(source).unwrap()[0].token_type {
+            TokenType::Str(value) => value.clone(),
+            TokenType::Bytes(bytes) => bytes.iter().map(|b| char::from(*b)).collect(),
+            other => panic!("Expected a string. Received: {:?}", other),
+        };
+        assert_eq!(value(r"'\a\b\f\n\r\t\v'"), "\x07\x08\x0c\n\r\t\x0b");
+        assert_eq!(value(r#"'\\ \' \"'"#), "\\ ' \"");
+        assert_eq!(value(r"'\101\0\7\1012'"), "A\0\x07A2");
+        assert_eq!(value(r"'\x41\u00e9\U0001f600'"), "Aé😀");
+        assert_eq!(value(r"b'\x41\xff\101'"), "A\u{ff}A");
+        // Escapes that do not decode are kept as written
+        assert_eq!(value(r"'\q \N{BULLET}'"), r"\q \N{BULLET}");
+        assert_eq!(
+            value(r"'\x4 \xZZ \x+f \u+041 \U0011ffff'"),
+            r"\x4 \xZZ \x+f \u+041 \U0011ffff"
+        );
+        assert_eq!(value(r"b'\u0041 \U00000041'"), r"\u0041 \U00000041");
+        // Raw strings keep every backslash
+        assert_eq!(value(r"r'\n\x41\''"), r"\n\x41\'");
+        assert_eq!(value(r"rb'\n'"), r"\n");
+    }
+
+    #[test]
+    fn test_non_ascii_bytes() {
+        for source in ["b'é'", "b'€'", r"rb'\é'", "b'''a\né'''"].iter() {
+            match lex(source) {
+                Err(LexError::NonAsciiBytes(at)) => assert_eq!((at.line, at.column), (1, 1)),
+                other => panic!(
+                    "Expected a bytes error for {:?}. Received: {:?}",
+                    source, other
+                ),
+            }
+        }
+        assert!(lex(r"b'\xe9' + 'é'").is_ok());
+    }
+
+    #[test]
+    fn test_unterminated_string() {
+        match lex("a\n'abc\n'") {
+            Err(LexError::UnterminatedString(at)) => assert_eq!((at.line, at.column), (2, 1)),
+            other => panic!("Expected an unterminated string. Received: {:?}", other),
+        }
+        assert!(lex("'''abc").is_err());
+    }
+
+    #[test]
+    fn test_string_positions() {
+        let tokens = lex("'''a\nbc''' d").unwrap();
+        assert_eq!((tokens[0].start.line, tokens[0].start.column), (1, 1));
+        assert_eq!((tokens[0].end.line, tokens[0].end.column), (2, 5));
+        assert_eq!((tokens[1].start.line, tokens[1].start.column), (2, 7));
+    }
+
+    #[test]
+    fn test_matches_ignoring_payload() {
+        use super::TokenType::*;
+        let a = Name("a".to_owned());
+        let b = Name("b".to_owned());
+        assert!(a.matches_ignoring_payload(&b));
+        assert_ne!(a, b);
+        assert!(Str("a".to_owned()).matches_ignoring_payload(&Str("b".to_owned())));
+        assert!(!a.matches_ignoring_payload(&Str("a".to_owned())));
+        assert!(Plus.matches_ignoring_payload(&Plus));
+        assert!(!Plus.matches_ignoring_payload(&Minus));
+    }
+
+    #[test]
+    fn test_name_equals_str() {
+        let name = TokenType::Name("foo".to_owned());
+        assert!(name == *"foo");
+        assert!(name == "foo");
+        assert!(name != "bar");
+        assert!(TokenType::Str("foo".to_owned()) != "foo");
+        assert!(TokenType::If != "if");
+    }
+
+    #[test]
+    fn test_kind_name() {
+        use super::TokenType::*;
+        assert_eq!(Plus.kind_name(), "Plus");
+        assert_eq!(StarStarEqual.kind_name(), "StarStarEqual");
+        assert_eq!(Name("x".to_owned()).kind_name(), "Name");
+        assert_eq!(Int(1).kind_name(), "Int");
+        assert_eq!(Str("s".to_owned()).kind_name(), "Str");
+        assert_eq!(None.kind_name(), "None");
+        assert_eq!(Indent.kind_name(), "Indent");
+        assert_eq!(Error('$').kind_name(), "Error");
+    }
+
+    #[test]
+    fn test_category() {
+        use super::TokenType::*;
+        assert_eq!(StarStar.category(), TokenCategory::Operator);
+        assert_eq!(RightBrace.category(), TokenCategory::Bracket);
+        assert_eq!(Name("a".to_owned()).category(), TokenCategory::Name);
+        assert_eq!(Str("a".to_owned()).category(), TokenCategory::Literal);
+        assert_eq!(Else.category(), TokenCategory::Keyword);
+        assert_eq!(Dedent.category(), TokenCategory::Layout);
+    }
+
+    /// One token of every variant, in declaration order
+    fn every_token_type() -> Vec<TokenType> {
+        use super::TokenType::*;
+        vec![
+            Plus,
+            Minus,
+            Star,
+            StarStar,
+            Slash,
+            SlashSlash,
+            Percent,
+            At,
+            Amp,
+            Pipe,
+            Caret,
+            Tilde,
+            LeftShift,
+            RightShift,
+            EqualEqual,
+            NotEqual,
+            Less,
+            Greater,
+            LessEqual,
+            GreaterEqual,
+            Equal,
+            PlusEqual,
+            MinusEqual,
+            StarEqual,
+            StarStarEqual,
+            SlashEqual,
+            SlashSlashEqual,
+            PercentEqual,
+            AtEqual,
+            AmpEqual,
+            PipeEqual,
+            CaretEqual,
+            LeftShiftEqual,
+            RightShiftEqual,
+            ColonEqual,
+            LeftParen,
+            RightParen,
+            LeftBracket,
+            RightBracket,
+            LeftBrace,
+            RightBrace,
+            Colon,
+            Dot,
+            Comma,
+            Semicolon,
+            Arrow,
+            Ellipsis,
+            Name("x".to_owned()),
+            Str("x".to_owned()),
+            Bytes(vec![b'x']),
+            FString("x".to_owned()),
+            Int(1),
+            Float(1.0),
+            Imaginary(1.0),
+            Op("x".to_owned()),
+            Error('$'),
+            False,
+            None,
+            True,
+            And,
+            As,
+            Assert,
+            Async,
+            Await,
+            Break,
+            Class,
+            Continue,
+            Def,
+            Del,
+            Elif,
+            Else,
+            Except,
+            Finally,
+            For,
+            From,
+            Global,
+            If,
+            Import,
+            In,
+            Is,
+            Lambda,
+            Nonlocal,
+            Not,
+            Or,
+            Pass,
+            Raise,
+            Return,
+            Try,
+            While,
+            With,
+            Yield,
+            Newline,
+            Indent,
+            Dedent,
+            Nl,
+        ]
+    }
+
+    /// The position of the variant in `every_token_type`.
+    ///
+    /// There is no wildcard, so a new variant does not compile until it is
+    /// added here and to `every_token_type`, which the test below checks.
+    fn variant_index(token_type: &TokenType) -> usize {
+        use super::TokenType::*;
+        match token_type {
+            Plus => 0,
+            Minus => 1,
+            Star => 2,
+            StarStar => 3,
+            Slash => 4,
+            SlashSlash => 5,
+            Percent => 6,
+            At => 7,
+            Amp => 8,
+            Pipe => 9,
+            Caret => 10,
+            Tilde => 11,
+            LeftShift => 12,
+            RightShift => 13,
+            EqualEqual => 14,
+            NotEqual => 15,
+            Less => 16,
+            Greater => 17,
+            LessEqual => 18,
+            GreaterEqual => 19,
+            Equal => 20,
+            PlusEqual => 21,
+            MinusEqual => 22,
+            StarEqual => 23,
+            StarStarEqual => 24,
+            SlashEqual => 25,
+            SlashSlashEqual => 26,
+            PercentEqual => 27,
+            AtEqual => 28,
+            AmpEqual => 29,
+            PipeEqual => 30,
+            CaretEqual => 31,
+            LeftShiftEqual => 32,
+            RightShiftEqual => 33,
+            ColonEqual => 34,
+            LeftParen => 35,
+            RightParen => 36,
+            LeftBracket => 37,
+            RightBracket => 38,
+            LeftBrace => 39,
+            RightBrace => 40,
+            Colon => 41,
+            Dot => 42,
+            Comma => 43,
+            Semicolon => 44,
+            Arrow => 45,
+            Ellipsis => 46,
+            Name(_) => 47,
+            Str(_) => 48,
+            Bytes(_) => 49,
+            FString(_) => 50,
+            Int(_) => 51,
+            Float(_) => 52,
+            Imaginary(_) => 53,
+            Op(_) => 54,
+            Error(_) => 55,
+            False => 56,
+            None => 57,
+            True => 58,
+            And => 59,
+            As => 60,
+            Assert => 61,
+            Async => 62,
+            Await => 63,
+            Break => 64,
+            Class => 65,
+            Continue => 66,
+            Def => 67,
+            Del => 68,
+            Elif => 69,
+            Else => 70,
+            Except => 71,
+            Finally => 72,
+            For => 73,
+            From => 74,
+            Global => 75,
+            If => 76,
+            Import => 77,
+            In => 78,
+            Is => 79,
+            Lambda => 80,
+            Nonlocal => 81,
+            Not => 82,
+            Or => 83,
+            Pass => 84,
+            Raise => 85,
+            Return => 86,
+            Try => 87,
+            While => 88,
+            With => 89,
+            Yield => 90,
+            Newline => 91,
+            Indent => 92,
+            Dedent => 93,
+            Nl => 94,
+        }
+    }
+
+    #[test]
+    fn test_every_variant_is_classified() {
+        let every = every_token_type();
+        for (index, token_type) in every.iter().enumerate() {
+            assert_eq!(variant_index(token_type), index, "{:?}", token_type);
+        }
+
+        let mut names: Vec<&str> = every.iter().map(TokenType::kind_name).collect();
+        names.sort_unstable();
+        names.dedup();
+        assert_eq!(names.len(), every.len(), "kind names must be unique");
+
+        for token_type in &every {
+            let category = token_type.category();
+            match token_type.text() {
+                // Every spelled token lexes back to itself
+                Some(text) => {
+                    assert!(matches!(
+                        category,
+                        TokenCategory::Operator
+                            | TokenCategory::Bracket
+                            | TokenCategory::Delimiter
+                            | TokenCategory::Keyword
+                    ));
+                    assert_eq!(&lex(text).unwrap()[0].token_type, token_type);
+                }
+                Option::None => assert!(
+                    matches!(
+                        category,
+                        TokenCategory::Name
+                            | TokenCategory::Literal
+                            | TokenCategory::Layout
+                            | TokenCategory::Error
+                    ) || matches!(token_type, TokenType::Op(_)),
+                    "{:?}",
+                    token_type
+                ),
+            }
+            if token_type.operator_group().is_some() {
+                assert!(matches!(
+                    category,
+                    TokenCategory::Operator | TokenCategory::Keyword
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn test_operator_group() {
+        use super::TokenType::*;
+        assert_eq!(Plus.operator_group(), Some(OperatorGroup::Arithmetic));
+        assert_eq!(At.operator_group(), Some(OperatorGroup::Arithmetic));
+        assert_eq!(EqualEqual.operator_group(), Some(OperatorGroup::Comparison));
+        assert_eq!(Is.operator_group(), Some(OperatorGroup::Comparison));
+        assert_eq!(Amp.operator_group(), Some(OperatorGroup::Bitwise));
+        assert_eq!(And.operator_group(), Some(OperatorGroup::Boolean));
+        assert_eq!(Equal.operator_group(), Some(OperatorGroup::Assignment));
+        assert_eq!(
+            StarStarEqual.operator_group(),
+            Some(OperatorGroup::Assignment)
+        );
+        assert_eq!(Colon.operator_group(), Option::None);
+        assert_eq!(If.operator_group(), Option::None);
+        assert_eq!(Op("+".to_owned()).operator_group(), Option::None);
+    }
+
+    #[test]
+    fn test_numeric_value() {
+        let value = |source: &str| lex(source).unwrap()[0].token_type.numeric_value();
+        assert_eq!(value("1000"), Some(NumericValue::Int(1000)));
+        assert_eq!(value("1_000"), value("1000"));
+        assert_eq!(value("0x3e8"), value("1000"));
+        assert_eq!(value("0o1750"), value("1000"));
+        assert_eq!(value("1000.0"), Some(NumericValue::Float(1000.0)));
+        assert_ne!(value("1000.0"), value("1000"));
+        assert_eq!(value("1e3"), value("1000.0"));
+        assert_eq!(value("1000j"), Some(NumericValue::Imaginary(1000.0)));
+        assert_eq!(value("x"), Option::None);
+    }
+
+    #[test]
+    fn test_byte_columns() {
+        let options = LexerOptions {
+            byte_columns: true,
+            ..LexerOptions::default()
+        };
+        let tokens = lex_with_options("é = 'ü' + b\nc", &options).unwrap();
+        let columns: Vec<(u64, Option<u64>)> = tokens
+            .iter()
+            .map(|t| (t.start.column, t.start.byte_column))
+            .collect();
+        assert_eq!(
+            columns,
+            vec![
+                (1, Some(1)),
+                (3, Some(4)),
+                (5, Some(6)),
+                (9, Some(11)),
+                (11, Some(13)),
+                (12, Some(14)),
+                (1, Some(1))
+            ]
+        );
+        assert_eq!(tokens[2].end.byte_column, Some(9));
+        assert_eq!(lex("é").unwrap()[0].start.byte_column, Option::None);
+        let (tokens, _) = lex_all(
+            "ü $ a",
+            &LexerOptions {
+                emit_error_tokens: true,
+                ..options
+            },
+        );
+        assert_eq!(tokens[2].start.byte_column, Some(6));
+    }
+
+    #[test]
+    fn test_location_ordering() {
+        assert!(Location::new(1, 5) < Location::new(2, 1));
+        assert!(Location::new(2, 3) < Location::new(2, 4));
+        assert_eq!(Location::new(3, 2), Location::new(3, 2));
+
+        let mut locations = vec![
+            Location::new(2, 1),
+            Location::new(1, 9),
+            Location::new(1, 2),
+        ];
+        locations.sort();
+        assert_eq!(
+            locations,
+            vec![
+                Location::new(1, 2),
+                Location::new(1, 9),
+                Location::new(2, 1)
+            ]
+        );
+
+        // The byte column does not change how a location compares or hashes
+        let options = LexerOptions {
+            byte_columns: true,
+            ..LexerOptions::default()
+        };
+        let with_bytes = lex_with_options("é = 1", &options).unwrap();
+        let plain = lex("é = 1").unwrap();
+        assert_eq!(with_bytes[1].start, plain[1].start);
+        assert_eq!(with_bytes[1].start.cmp(&plain[1].start), Ordering::Equal);
+        let set: std::collections::HashSet<Location> = vec![with_bytes[1].start, plain[1].start]
+            .into_iter()
+            .collect();
+        assert_eq!(set.len(), 1);
+    }
+
+    #[test]
+    fn test_int_token() {
+        use super::TokenType::*;
+        lex_test!("100", Int(100));
+        lex_test!("1 + 23", Int(1), Plus, Int(23));
+        lex_test!("100 l", Int(100), Name("l".to_owned()));
+        lex_test!("a1 _b", Name("a1".to_owned()), Name("_b".to_owned()));
+        assert!(matches!(
+            lex("99999999999999999999"),
+            Err(LexError::IntegerOverflow(_))
+        ));
+    }
+
+    #[test]
+    fn test_number_token() {
+        use super::TokenType::*;
+        lex_test!("0x_ff 0o17 0b101", Int(255), Int(15), Int(5));
+        lex_test!("1_000", Int(1000));
+        lex_test!(
+            "1.5 .5 1. 1e3 2.5E-2",
+            Float(1.5),
+            Float(0.5),
+            Float(1.0),
+            Float(1000.0),
+            Float(0.025)
+        );
+        lex_test!("3j 1.5J", Imaginary(3.0), Imaginary(1.5));
+        lex_test!("1else", Int(1), Else);
+        lex_test!("1.real", Float(1.0), Name("real".to_owned()));
+        assert!(matches!(lex("0xzz"), Err(LexError::InvalidNumber(_))));
+    }
+
+    #[test]
+    fn test_operator_tokens() {
+        use super::TokenType::*;
+        lex_test!(
+            "+= -= *= **= /= //= %= @=",
+            PlusEqual,
+            MinusEqual,
+            StarEqual,
+            StarStarEqual,
+            SlashEqual,
+            SlashSlashEqual,
+            PercentEqual,
+            AtEqual
+        );
+        lex_test!(
+            "&= |= ^= <<= >>= :=",
+            AmpEqual,
+            PipeEqual,
+            CaretEqual,
+            LeftShiftEqual,
+            RightShiftEqual,
+            ColonEqual
+        );
+        lex_test!(
+            "== != < > <= >=",
+            EqualEqual,
+            NotEqual,
+            Less,
+            Greater,
+            LessEqual,
+            GreaterEqual
+        );
+        lex_test!(
+            "// % @ & | ^ ~ << >>",
+            SlashSlash,
+            Percent,
+            At,
+            Amp,
+            Pipe,
+            Caret,
+            Tilde,
+            LeftShift,
+            RightShift
+        );
+        lex_test!("-> ... ; ,", Arrow, Ellipsis, Semicolon, Comma);
+        lex_test!("[]{}", LeftBracket, RightBracket, LeftBrace, RightBrace);
+        assert!(matches!(
+            lex("!"),
+            Err(LexError::UnexpectedToken('!', _, _))
+        ));
+    }
+
+    #[test]
+    fn test_keyword_tokens() {
+        use super::TokenType::*;
+        lex_test!("False None True and as", False, None, True, And, As);
+        lex_test!(
+            "async await lambda nonlocal yield",
+            Async,
+            Await,
+            Lambda,
+            Nonlocal,
+            Yield
+        );
+    }
+
+    #[test]
+    fn test_string_prefixes() {
+        use super::TokenType::*;
+        lex_test!(r"r'a\nb'", Str(r"a\nb".to_owned()));
+        lex_test!(r"r'\''", Str(r"\'".to_owned()));
+        lex_test!("u'a'", Str("a".to_owned()));
+        lex_test!(r"b'a\xff'", Bytes(vec![b'a', 0xff]));
+        lex_test!(r"rb'\x'", Bytes(br"\x".to_vec()));
+        lex_test!("f'{a!r}'", FString("{a!r}".to_owned()));
+        lex_test!(r"'\x41\101\u00e9'", Str("AAé".to_owned()));
+        lex_test!("rx'a'", Name("rx".to_owned()), Str("a".to_owned()));
+    }
+
+    #[test]
+    fn test_fstring_edge_cases() {
+        use super::TokenType::*;
+        lex_test!("f\"\"", FString(String::new()));
+        lex_test!("f''''''", FString(String::new()));
+        // Adjacent strings stay separate tokens, joining them is left to the parser
+        lex_test!(
+            "f\"a\" f\"{x}\"",
+            FString("a".to_owned()),
+            FString("{x}".to_owned())
+        );
+        lex_test!("f\"a\" \"b\"", FString("a".to_owned()), Str("b".to_owned()));
+        lex_test!(
+            "\"a\" f\"{x}\"",
+            Str("a".to_owned()),
+            FString("{x}".to_owned())
+        );
+        lex_test!("\"a\"f\"b\"", Str("a".to_owned()), FString("b".to_owned()));
+    }
+
+    #[test]
+    fn test_string_prefix_matrix() {
+        use super::TokenType::*;
+        let valid = [
+            ("r", "r'a'"),
+            ("r", "R'a'"),
+            ("u", "U'a'"),
+            ("b", "B'a'"),
+            ("f", "F'a'"),
+            ("rb", "rb'a'"),
+            ("rb", "Rb'a'"),
+            ("rb", "bR'a'"),
+            ("rb", "BR'a'"),
+            ("rf", "fr'a'"),
+            ("rf", "Rf'a'"),
+            ("rf", "FR'a'"),
+        ];
+        for (kind, source) in valid.iter() {
+            let tokens = lex(source).unwrap_or_else(|e| panic!("{}: {}", source, e));
+            let expected = match *kind {
+                "r" | "u" => Str("a".to_owned()),
+                "b" | "rb" => Bytes(b"a".to_vec()),
+                _ => FString("a".to_owned()),
+            };
+            assert_eq!(tokens[0].token_type, expected, "{}", source);
+        }
+
+        let invalid = [
+            "bf'a'", "FB'a'", "ub'a'", "Ur'a'", "uf'a'", "rr'a'", "brb'a'",
+        ];
+        for source in invalid.iter() {
+            match lex(source) {
+                Err(LexError::InvalidStringPrefix { prefix, at }) => {
+                    assert_eq!(prefix, source[..source.find('\'').unwrap()]);
+                    assert_eq!((at.line, at.column), (1, 1));
+                }
+                other => panic!("{}: expected an invalid prefix, got {:?}", source, other),
+            }
+        }
+
+        // Other names before a quote are still a name and then a string
+        lex_test!("x'a'", Name("x".to_owned()), Str("a".to_owned()));
+
+        // Collecting errors still lexes the string
+        let (tokens, errors) = lex_all("bf'a' + 1", &LexerOptions::default());
+        assert_eq!(errors.len(), 1);
+        assert_eq!(tokens[0].token_type, Bytes(b"a".to_vec()));
+        assert_eq!(tokens[1].token_type, Plus);
+    }
+
+    #[test]
+    fn test_line_continuation() {
+        use super::TokenType::*;
+        lex_test!(
+            "a + \\\n    b",
+            Name("a".to_owned()),
+            Plus,
+            Name("b".to_owned())
+        );
+        lex_test!("a \\\r\nb", Name("a".to_owned()), Name("b".to_owned()));
+        assert!(matches!(
+            lex("a \\ b"),
+            Err(LexError::UnexpectedToken('\\', _, _))
+        ));
+    }
+
+    #[test]
+    fn test_generic_operators() {
+        use super::TokenType::*;
+        let options = LexerOptions {
+            generic_operators: true,
+            ..LexerOptions::default()
+        };
+        let types: Vec<TokenType> = lex_with_options("a + b ** (2)", &options)
+            .unwrap()
+            .into_iter()
+            .map(|token| token.token_type)
+            .collect();
+        assert_eq!(
+            types,
+            vec![
+                Name("a".to_owned()),
+                Op("+".to_owned()),
+                Name("b".to_owned()),
+                Op("**".to_owned()),
+                Op("(".to_owned()),
+                Int(2),
+                Op(")".to_owned()),
+            ]
+        );
+        lex_test!("a + b", Name("a".to_owned()), Plus, Name("b".to_owned()));
+    }
+
+    #[test]
+    fn test_token_text() {
+        use super::TokenType::*;
+        assert_eq!(StarStarEqual.text(), Some("**="));
+        assert_eq!(Nonlocal.text(), Some("nonlocal"));
+        assert_eq!(Name("a".to_owned()).text(), Option::None);
+        assert_eq!(Indent.text(), Option::None);
+    }
+
+    #[test]
+    fn test_all_keywords_resolve() {
+        assert_eq!(KEYWORDS.len(), 35);
+        for (text, keyword) in KEYWORDS.entries() {
+            assert_eq!(keyword.text(), Some(*text));
+            assert_eq!(keyword.category(), TokenCategory::Keyword);
+            let tokens = lex(text).unwrap();
+            assert_eq!(tokens.len(), 1);
+            assert_eq!(&tokens[0].token_type, keyword);
+        }
+        lex_test!("iff", TokenType::Name("iff".to_owned()));
+        lex_test!("é_if", TokenType::Name("é_if".to_owned()));
+    }
+
+    #[test]
+    fn test_recognize_keywords() {
+        use super::TokenType::*;
+        let options = LexerOptions {
+            recognize_keywords: false,
+            ..LexerOptions::default()
+        };
+        let types: Vec<TokenType> = lex_with_options("if else", &options)
+            .unwrap()
+            .into_iter()
+            .map(|token| token.token_type)
+            .collect();
+        assert_eq!(types, vec![Name("if".to_owned()), Name("else".to_owned())]);
+        lex_test!("if else", If, Else);
+    }
+
+    #[test]
+    fn test_keyword_map() {
+        use super::TokenType::*;
+        let mut keyword_map = HashMap::new();
+        keyword_map.insert("wenn".to_owned(), If);
+        let options = LexerOptions {
+            keyword_map: Some(keyword_map),
+            ..LexerOptions::default()
+        };
+        let types: Vec<TokenType> = lex_with_options("wenn if", &options)
+            .unwrap()
+            .into_iter()
+            .map(|token| token.token_type)
+            .collect();
+        assert_eq!(types, vec![If, Name("if".to_owned())]);
+    }
+
+    #[test]
+    fn test_lex_file() {
+        assert_eq!(lex_file("a.py", "a + b").unwrap().len(), 3);
+        let error = lex_file("pkg/b.py", "a\n'b").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "pkg/b.py: unterminated string starting at line 2, column 1"
+        );
+        assert!(matches!(error.error, LexError::UnterminatedString(_)));
+    }
+
+    #[test]
+    fn test_count_tokens() {
+        let sources = [
+            "",
+            "a",
+            "a + b\n",
+            "def f(x):\n    return x ** 2\n\n\nprint(f(3))",
+            "(a,\n b)\n# comment\nc = 'd'",
+        ];
+        for source in sources.iter() {
+            assert_eq!(count_tokens(source).unwrap(), lex(source).unwrap().len());
+        }
+        assert!(count_tokens("a $").is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let source = "def f(x=0.1, *y):\n    return x ** 1e999 + 2.5j, b'\\xff', 'a'\n";
+        let tokens = lex(source).unwrap();
+        let json = serde_json::to_string(&tokens).unwrap();
+        let round_trip: Vec<Token> = serde_json::from_str(&json).unwrap();
+        assert_eq!(round_trip, tokens);
+    }
+
+    #[test]
+    fn test_python2_long_literal() {
+        match lex("100L") {
+            Err(LexError::Python2LongLiteral { at }) => assert_eq!((at.line, at.column), (1, 4)),
+            other => panic!("Expected a long literal error. Received: {:?}", other),
+        }
+        match lex("a\n7l") {
+            Err(LexError::Python2LongLiteral { at }) => assert_eq!((at.line, at.column), (2, 2)),
+            other => panic!("Expected a long literal error. Received: {:?}", other),
+        }
+        for source in ["0x10L", "0o17l", "0b1_0L"].iter() {
+            match lex(source) {
+                Err(LexError::Python2LongLiteral { at }) => {
+                    assert_eq!(at.column as usize, source.len(), "{}", source)
+                }
+                other => panic!("Expected a long literal error. Received: {:?}", other),
+            }
+        }
+        // Only a final `l` is a suffix
+        assert!(matches!(lex("0x1l2"), Err(LexError::InvalidNumber(_))));
+    }
+
+    #[test]
+    fn test_fold_identifier_case() {
+        let folded = LexerOptions {
+            fold_identifier_case: true,
+            ..LexerOptions::default()
+        };
+        let upper = lex_with_options("Foo 'Bar'", &folded).unwrap();
+        let lower = lex_with_options("foo", &folded).unwrap();
+        assert_eq!(upper[0].token_type, lower[0].token_type);
+        assert_eq!(upper[1].token_type, TokenType::Str("Bar".to_owned()));
+
+        let upper = lex("Foo").unwrap();
+        let lower = lex("foo").unwrap();
+        assert_ne!(upper[0].token_type, lower[0].token_type);
+
+        // Keywords keep their case, and names that only look like them stay names
+        let tokens = lex_with_options("True or None or IF", &folded).unwrap();
+        let types: Vec<_> = tokens.iter().map(|t| t.token_type.clone()).collect();
+        assert_eq!(
+            types,
+            vec![
+                TokenType::True,
+                TokenType::Or,
+                TokenType::None,
+                TokenType::Or,
+                TokenType::Name("if".to_owned())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_empty_input() {
+        for source in &["", "   ", "\n\n", " \t\n  \n", "# only a comment\n", "\r\n"] {
+            match lex(source) {
+                Ok(tokens) => assert!(tokens.is_empty(), "{:?} gave {:?}", source, tokens),
+                Err(e) => panic!("Did not lex {:?}. Received: {:?}", source, e),
+            }
+        }
+    }
+
+    #[test]
+    fn test_lex_all_bad_dedent_recovery() {
+        use super::TokenType::*;
+        let source = "if a:\n    b\n  c\n  d\ne\n";
+        let (tokens, errors) = lex_all(source, &LexerOptions::default());
+        let types: Vec<TokenType> = tokens.iter().map(|t| t.token_type.clone()).collect();
+        assert_eq!(
+            types,
+            vec![
+                If,
+                Name("a".to_owned()),
+                Colon,
+                Newline,
+                Indent,
+                Name("b".to_owned()),
+                Newline,
+                Dedent,
+                Indent,
+                Name("c".to_owned()),
+                Newline,
+                Name("d".to_owned()),
+                Newline,
+                Dedent,
+                Name("e".to_owned()),
+                Newline,
+            ]
+        );
+        match &errors[..] {
+            [LexError::BadDedent { found, at, .. }] => {
+                assert_eq!(*found, 2);
+                assert_eq!((at.line, at.column), (3, 3));
+            }
+            other => panic!("Expected one bad dedent. Received: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_positions_suite() {
+        // Positions produced before the lexer moved to a single cursor
+        let suite = [
+            (
+                "a = 1 + 2\n",
+                "1:1-1:1 1:3-1:3 1:5-1:5 1:7-1:7 1:9-1:9 1:10-1:10",
+            ),
+            (
+                "if a:\n    b += 'x'\n\tc\n",
+                "1:1-1:2 1:4-1:4 1:5-1:5 1:6-1:6 2:5-2:5 2:5-2:5 2:7-2:8 2:10-2:12 2:13-2:13 \
+                 3:2-3:2 3:2-3:2 3:3-3:3 4:1-4:1 4:1-4:1",
+            ),
+            (
+                "s = '''one\ntwo\n  three''' + b\n",
+                "1:1-1:1 1:3-1:3 1:5-3:10 3:12-3:12 3:14-3:14 3:15-3:15",
+            ),
+            (
+                "x = (1,\n     2)\ny = 3 \\\n  + 4\n",
+                "1:1-1:1 1:3-1:3 1:5-1:5 1:6-1:6 1:7-1:7 2:6-2:6 2:7-2:7 2:8-2:8 3:1-3:1 \
+                 3:3-3:3 3:5-3:5 4:3-4:3 4:5-4:5 4:6-4:6",
+            ),
+            (
+                "f(r'a\\\nb', 'c\\\nd', \"é\")\n",
+                "1:1-1:1 1:2-1:2 1:3-2:2 2:3-2:3 2:5-3:2 3:3-3:3 3:5-3:7 3:8-3:8 3:9-3:9",
+            ),
+            (
+                "  # comment\nđ = 0x1f ** -2.5j\n",
+                "2:1-2:1 2:3-2:3 2:5-2:8 2:10-2:11 2:13-2:13 2:14-2:17 2:18-2:18",
+            ),
+            (
+                "a->b>>=c//=d...\n",
+                "1:1-1:1 1:2-1:3 1:4-1:4 1:5-1:7 1:8-1:8 1:9-1:11 1:12-1:12 1:13-1:15 1:16-1:16",
+            ),
+        ];
+        for (source, expected) in suite.iter() {
+            let positions: Vec<String> = lex(source)
+                .unwrap()
+                .iter()
+                .map(|t| {
+                    format!(
+                        "{}:{}-{}:{}",
+                        t.start.line, t.start.column, t.end.line, t.end.column
+                    )
+                })
+                .collect();
+            assert_eq!(positions.join(" "), *expected, "{:?}", source);
+        }
+
+        let (_, errors) = lex_all("a $ b\n 'x", &LexerOptions::default());
+        match &errors[..] {
+            [LexError::UnexpectedToken('$', at, _), LexError::UnterminatedString(start)] => {
+                assert_eq!((at.line, at.column), (1, 3));
+                assert_eq!((start.line, start.column), (2, 2));
+            }
+            other => panic!("Expected two errors. Received: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_scanner() {
+        let mut scanner = Scanner::new("é1 ab", 0);
+        assert_eq!(scanner.peek(), Some('é'));
+        assert_eq!(scanner.peek_next(), Some('1'));
+        scanner.bump();
+        assert_eq!(scanner.offset, 2);
+        assert_eq!(scanner.eat_while(|c| c.is_ascii_digit()), "1");
+        assert_eq!(scanner.eat_while(|c| c.is_ascii_digit()), "");
+        assert_eq!(scanner.peek_nth(2), Some('b'));
+        scanner.bump();
+        assert_eq!(scanner.eat_while(char::is_alphabetic), "ab");
+        assert_eq!(scanner.peek(), Option::None);
+        scanner.bump();
+        assert_eq!(scanner.rest(), "");
+    }
+
+    #[test]
+    fn test_location_advance() {
+        let mut location = Location::new(3, 4);
+        location.advance_column(2);
+        assert_eq!((location.line, location.column), (3, 6));
+        assert_eq!(location.previous_column().column, 5);
+        location.advance_line();
+        assert_eq!((location.line, location.column), (4, 1));
+    }
+
+    #[test]
+    fn test_lex_range() {
+        use super::TokenType::*;
+        let source = "a\nif b:\n    ccc + d\ne\n";
+        let plus = source.find('+').unwrap();
+        let tokens = lex_range(source, plus..plus + 3).unwrap();
+        let types: Vec<TokenType> = tokens.iter().map(|t| t.token_type.clone()).collect();
+        assert_eq!(
+            types,
+            vec![Name("ccc".to_owned()), Plus, Name("d".to_owned()), Newline]
+        );
+        assert_eq!((tokens[0].start.line, tokens[0].start.column), (3, 5));
+        assert_eq!((tokens[2].start.line, tokens[2].start.column), (3, 11));
+
+        // A range starting in the middle of a name still lexes the whole name
+        let tokens = lex_range(source, plus - 3..source.len()).unwrap();
+        assert_eq!(tokens[0].token_type, Name("ccc".to_owned()));
+        assert_eq!((tokens[4].start.line, tokens[4].start.column), (4, 1));
+
+        assert!(lex_range(source, 1000..2000).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_byte_ranges() {
+        let options = LexerOptions {
+            byte_ranges: true,
+            ..LexerOptions::default()
+        };
+        let source = "if é:\n    'ü'\n";
+        let tokens = lex_with_options(source, &options).unwrap();
+        let ranges: Vec<Range<usize>> = tokens.iter().map(|t| t.byte_range().unwrap()).collect();
+        assert_eq!(
+            ranges,
+            vec![0..2, 3..5, 5..6, 6..7, 11..11, 11..15, 15..16, 16..16]
+        );
+        assert_eq!(&source[ranges[5].clone()], "'ü'");
+        assert_eq!(lex(source).unwrap()[0].byte_range(), Option::None);
+    }
+
+    #[test]
+    fn test_zero_based_positions() {
+        let options = LexerOptions {
+            zero_based_positions: true,
+            byte_columns: true,
+            ..LexerOptions::default()
+        };
+        let tokens = lex_with_options("é = 1\nif a:\n    b\n", &options).unwrap();
+        assert_eq!((tokens[0].start.line, tokens[0].start.column), (0, 0));
+        assert_eq!((tokens[1].start.line, tokens[1].start.column), (0, 2));
+        assert_eq!(tokens[1].start.byte_column, Some(3));
+        // The indented name is on the third line, after four spaces
+        let b = tokens
+            .iter()
+            .find(|token| token.token_type == TokenType::Name("b".to_owned()))
+            .unwrap();
+        assert_eq!((b.start.line, b.start.column), (2, 4));
+
+        match lex_with_options("a $", &options) {
+            Err(LexError::UnexpectedToken('$', at, _)) => assert_eq!((at.line, at.column), (0, 2)),
+            other => panic!("expected an unexpected token, got {:?}", other),
+        }
+        let (_, errors) = lex_all("a\n$", &options);
+        match &errors[..] {
+            [LexError::UnexpectedToken('$', at, _)] => assert_eq!((at.line, at.column), (1, 0)),
+            other => panic!("expected one unexpected token, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_lex_first_lines() {
+        use super::TokenType::*;
+        let source = "if a:\n    b\n    c\nd\ne $\n";
+        let types: Vec<TokenType> = lex_first_lines(source, 2)
+            .unwrap()
+            .into_iter()
+            .map(|token| token.token_type)
+            .collect();
+        assert_eq!(
+            types,
+            vec![
+                If,
+                Name("a".to_owned()),
+                Colon,
+                Newline,
+                Indent,
+                Name("b".to_owned()),
+                Newline,
+                Dedent
+            ]
+        );
+        assert!(lex_first_lines(source, 0).unwrap().is_empty());
+        assert_eq!(lex_first_lines(source, 4).unwrap().len(), 12);
+        assert!(lex_first_lines(source, 5).is_err());
+
+        // A string that starts in the lines is kept whole
+        let tokens = lex_first_lines("s = '''\n\n'''\nt\n", 1).unwrap();
+        assert_eq!(tokens[2].token_type, Str("\n\n".to_owned()));
+        assert_eq!(tokens.len(), 4);
+    }
+
+    #[test]
+    fn test_lex_all() {
+        use super::TokenType::*;
+        let (tokens, errors) = lex_all("a $ 1L\n'b\nc", &LexerOptions::default());
+        let types: Vec<TokenType> = tokens.iter().map(|t| t.token_type.clone()).collect();
+        assert_eq!(
+            types,
+            vec![Name("a".to_owned()), Int(1), Newline, Name("c".to_owned())]
+        );
+        assert!(matches!(errors[0], LexError::UnexpectedToken('$', _, _)));
+        assert!(matches!(errors[1], LexError::Python2LongLiteral { .. }));
+        assert!(matches!(errors[2], LexError::UnterminatedString(_)));
+        assert_eq!(errors.len(), 3);
+        assert_eq!((tokens[3].start.line, tokens[3].start.column), (3, 1));
+    }
+
+    #[test]
+    fn test_emit_error_tokens() {
+        use super::TokenType::*;
+        let options = LexerOptions {
+            emit_error_tokens: true,
+            ..LexerOptions::default()
+        };
+        let (tokens, errors) = lex_all("2 $ 3", &options);
+        let types: Vec<TokenType> = tokens.iter().map(|t| t.token_type.clone()).collect();
+        assert_eq!(types, vec![Int(2), Error('$'), Int(3)]);
+        assert_eq!((tokens[1].start.column, tokens[2].start.column), (3, 5));
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(errors[0], LexError::UnexpectedToken('$', _, _)));
+        assert!(lex_with_options("2 $ 3", &options).is_err());
+    }
+
+    #[test]
+    fn test_emit_nl_tokens() {
+        use super::TokenType::*;
+        let options = LexerOptions {
+            emit_nl_tokens: true,
+            ..LexerOptions::default()
+        };
+        let types: Vec<TokenType> = lex_with_options("\na\n\n# c\n(b,\n)\n", &options)
+            .unwrap()
+            .into_iter()
+            .map(|token| token.token_type)
+            .collect();
+        let b = Name("b".to_owned());
+        assert_eq!(
+            types,
+            vec![
+                Nl,
+                Name("a".to_owned()),
+                Newline,
+                Nl,
+                Nl,
+                LeftParen,
+                b,
+                Comma,
+                Nl,
+                RightParen,
+                Newline
+            ]
+        );
+    }
+
+    #[test]
+    fn test_max_tokens() {
+        let options = LexerOptions {
+            max_tokens: Some(5),
+            ..LexerOptions::default()
+        };
+        assert_eq!(lex_with_options("a + b + c", &options).unwrap().len(), 5);
+        match lex_with_options("a + b + c + d", &options) {
+            Err(LexError::TooManyTokens { limit }) => assert_eq!(limit, 5),
+            other => panic!("Expected too many tokens. Received: {:?}", other),
+        }
+        let long = "x = 1\n".repeat(1000);
+        assert!(matches!(
+            lex_with_options(&long, &options),
+            Err(LexError::TooManyTokens { limit: 5 })
+        ));
+        assert!(lex(&long).is_ok());
+    }
+
+    #[test]
+    fn test_max_source_bytes() {
+        let options = LexerOptions {
+            max_source_bytes: Some(16),
+            ..LexerOptions::default()
+        };
+        assert_eq!(lex_with_options("a = 1\n", &options).unwrap().len(), 4);
+        assert_eq!(
+            lex_with_options(&"x".repeat(16), &options).unwrap().len(),
+            1
+        );
+        let long = "x = 1\n".repeat(1000);
+        assert!(matches!(
+            lex_with_options(&long, &options),
+            Err(LexError::SourceTooLarge { limit: 16 })
+        ));
+        let error = lex_file_with_options("long.py", &long, &options).unwrap_err();
+        assert_eq!(error.file, "long.py");
+        assert!(matches!(
+            error.error,
+            LexError::SourceTooLarge { limit: 16 }
+        ));
+        // `lex_file` uses the default options, which have no limit
+        assert_eq!(lex_file("long.py", &long).unwrap().len(), 4000);
+        // The limit is checked before lexing, so even bad input gets this error
+        let (tokens, errors) = lex_all(&"$".repeat(17), &options);
+        assert!(tokens.is_empty());
+        assert!(matches!(
+            errors[..],
+            [LexError::SourceTooLarge { limit: 16 }]
+        ));
+    }
+
+    #[test]
+    fn test_identifier_policy() {
+        let options = LexerOptions {
+            identifier_start: Some(CharClass::new(|c| c == '$' || c.is_alphabetic())),
+            identifier_continue: Some(CharClass::new(|c| c == '$' || c.is_alphanumeric())),
+            ..LexerOptions::default()
+        };
+        let tokens = lex_with_options("$foo = a$b", &options).unwrap();
+        assert!(tokens[0].is_type(&TokenType::Name("$foo".to_owned())));
+        assert!(tokens[2].is_type(&TokenType::Name("a$b".to_owned())));
+        // `_` is no longer part of names, and keywords are still found
+        assert!(lex_with_options("_x", &options).is_err());
+        assert!(lex_with_options("if $x: pass", &options).unwrap()[0].is_type(&TokenType::If));
+
+        assert!(lex("$foo").is_err());
+        // By default names follow XID_Start and XID_Continue, as in Python
+        let tokens = lex("a\u{301}b = _1").unwrap();
+        assert!(tokens[0].is_type(&TokenType::Name("a\u{301}b".to_owned())));
+        assert!(tokens[2].is_type(&TokenType::Name("_1".to_owned())));
+        assert!(lex("\u{2e2f}").is_err());
+        assert!(lex("a\u{2e2f}").is_err());
+        assert!(lex("\u{301}a").is_err());
+        assert_eq!(
+            format!("{:?}", options.identifier_start),
+            "Some(CharClass(..))"
+        );
+    }
+
+    #[test]
+    fn test_max_errors() {
+        let source = "$ ".repeat(500);
+        let (_, errors) = lex_all(&source, &LexerOptions::default());
+        assert_eq!(errors.len(), 101);
+        assert!(matches!(
+            errors[100],
+            LexError::TooManyErrors { limit: 100 }
+        ));
+
+        let options = LexerOptions {
+            max_errors: 3,
+            ..LexerOptions::default()
+        };
+        let (_, errors) = lex_all(&source, &options);
+        assert_eq!(errors.len(), 4);
+        assert!(matches!(errors[3], LexError::TooManyErrors { limit: 3 }));
+
+        let (_, errors) = lex_all("$ $", &options);
+        assert_eq!(errors.len(), 2);
+
+        // Reaching the limit is fine, only an error past it stops lexing
+        let options = LexerOptions {
+            max_errors: 2,
+            ..LexerOptions::default()
+        };
+        let (_, errors) = lex_all("$ $", &options);
+        assert_eq!(errors.len(), 2);
+        assert!(errors
+            .iter()
+            .all(|e| matches!(e, LexError::UnexpectedToken(..))));
+
+        // Blocks open when lexing stops are still closed
+        let (tokens, errors) = lex_all("if a:\n    $ $ $\n", &options);
+        assert!(matches!(errors[2], LexError::TooManyErrors { limit: 2 }));
+        let indents = tokens
+            .iter()
+            .filter(|t| t.is_type(&TokenType::Indent))
+            .count();
+        let dedents = tokens
+            .iter()
+            .filter(|t| t.is_type(&TokenType::Dedent))
+            .count();
+        assert_eq!((indents, dedents), (1, 1));
+    }
+
+    #[test]
+    fn test_star_positions() {
+        use super::TokenType::*;
+        fn spans(source: &str) -> Vec<(TokenType, u64, u64)> {
+            lex(source)
+                .unwrap()
+                .into_iter()
+                .map(|t| (t.token_type, t.start.column, t.end.column))
+                .collect()
+        }
+        assert_eq!(spans("*/"), vec![(Star, 1, 1), (Slash, 2, 2)]);
+        assert_eq!(spans("**/"), vec![(StarStar, 1, 2), (Slash, 3, 3)]);
+        assert_eq!(spans("*"), vec![(Star, 1, 1)]);
+        assert_eq!(spans("**"), vec![(StarStar, 1, 2)]);
+        assert_eq!(spans("***"), vec![(StarStar, 1, 2), (Star, 3, 3)]);
+        assert_eq!(
+            spans("**=*="),
+            vec![(StarStarEqual, 1, 3), (StarEqual, 4, 5)]
+        );
+        assert_eq!(
+            spans("a*b"),
+            vec![
+                (Name("a".to_owned()), 1, 1),
+                (Star, 2, 2),
+                (Name("b".to_owned()), 3, 3)
+            ]
+        );
+        assert_eq!(spans("*\nb")[2], (Name("b".to_owned()), 1, 1));
+    }
+
+    #[test]
+    fn test_token_positions() {
+        let tokens = lex("ab +\n  c").unwrap();
+        assert_eq!((tokens[0].start.line, tokens[0].start.column), (1, 1));
+        assert_eq!((tokens[0].end.line, tokens[0].end.column), (1, 2));
+        assert_eq!((tokens[1].start.line, tokens[1].start.column), (1, 4));
+        assert_eq!((tokens[2].start.line, tokens[2].start.column), (1, 5));
+        assert_eq!((tokens[3].start.line, tokens[3].start.column), (2, 3));
+    }
+
+    #[test]
+    fn test_lex_iter_matches_lex() {
+        let sources = [
+            "",
+            "a = 1\n",
+            "if a:\n    b = [1,\n         2]\n\n    # done\nc = 'x' \\\n    'y'\n",
+            "def f():\n    if x:\n        return '''a\nb'''\n",
+            "\n\n  \nx",
+        ];
+        for source in sources.iter() {
+            let streamed: Tokens = lex_iter(source).collect::<Result<_, _>>().unwrap();
+            assert_eq!(streamed, Tokens::from(lex(source).unwrap()), "{:?}", source);
+        }
+
+        // The tokens before an error come first, and nothing comes after it
+        let mut lexer = lex_iter("a\nb $\nc\n");
+        assert!(lexer
+            .next()
+            .unwrap()
+            .unwrap()
+            .is_type(&TokenType::Name("a".to_owned())));
+        assert!(lexer.next().unwrap().unwrap().is_type(&TokenType::Newline));
+        assert!(lexer
+            .next()
+            .unwrap()
+            .unwrap()
+            .is_type(&TokenType::Name("b".to_owned())));
+        assert!(lexer.next().unwrap().is_err());
+        assert!(lexer.next().is_none());
+    }
+
+    #[test]
+    fn test_lexer_size_hint() {
+        let sources = [
+            "",
+            "x",
+            "a:\n b",
+            "if a:\n    if b:\n        if c:\n            d",
+            "f(a,\n  b)\n\n  # c\n",
+            "x = 1 $\n",
+        ];
+        for source in sources.iter() {
+            let mut lexer = Lexer::new(source);
+            let mut left = Lexer::new(source).count();
+            loop {
+                let (lower, upper) = lexer.size_hint();
+                assert!(lower <= left, "{:?}: {} > {}", source, lower, left);
+                assert!(
+                    upper.unwrap() >= left,
+                    "{:?}: {:?} < {}",
+                    source,
+                    upper,
+                    left
+                );
+                if lexer.next().is_none() {
+                    break;
+                }
+                left -= 1;
+            }
+            assert_eq!(lexer.size_hint(), (0, Some(0)));
+        }
+
+        let source = "a = [1, 2]\n".repeat(100);
+        let tokens: Vec<_> = Lexer::new(&source).collect();
+        assert_eq!(tokens.len(), lex(&source).unwrap().len());
+    }
+
+    #[test]
+    fn test_lexer_with_options() {
+        let source = "if é:\n    b = 'ü' + c\n";
+        let options = LexerOptions {
+            zero_based_positions: true,
+            byte_columns: true,
+            byte_ranges: true,
+            ..LexerOptions::default()
+        };
+        let streamed: Vec<Token> = Lexer::with_options(source, &options)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(streamed, lex_with_options(source, &options).unwrap());
+
+        let options = LexerOptions {
+            max_source_bytes: Some(4),
+            ..LexerOptions::default()
+        };
+        let mut lexer = Lexer::with_options(source, &options);
+        assert_eq!(lexer.size_hint(), (1, Some(1)));
+        assert!(matches!(
+            lexer.next(),
+            Some(Err(LexError::SourceTooLarge { limit: 4 }))
+        ));
+        assert!(lexer.next().is_none());
+    }
+
+    #[test]
+    fn test_tokens_extend() {
+        let mut tokens: Tokens = lex("a\n").unwrap().into_iter().collect();
+        tokens.extend(lex("b\n").unwrap());
+        assert_eq!(tokens.len(), 4);
+        assert!(tokens[2].is_type(&TokenType::Name("b".to_owned())));
+        assert_eq!(tokens.into_vec().len(), 4);
+    }
+
+    #[test]
+    fn test_lex_with_callback() {
+        let source = "def f(a):\n    return a + 1\n";
+        let mut count = 0;
+        let mut names = Vec::new();
+        let tokens = lex_with_callback(source, &LexerOptions::default(), &mut |token| {
+            count += 1;
+            if let TokenType::Name(name) = token.token_type() {
+                names.push(name.clone());
+            }
+        })
+        .unwrap();
+        assert_eq!(count, tokens.len());
+        assert_eq!(names, ["f", "a", "a"]);
+
+        // Tokens before an error are still seen
+        let mut count = 0;
+        assert!(lex_with_callback("a b $", &LexerOptions::default(), &mut |_| count += 1).is_err());
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn test_byte_order_mark_skipped() {
+        let tokens = lex("\u{feff}if a:\n    b\n").unwrap();
+        assert_eq!(tokens, lex("if a:\n    b\n").unwrap());
+        assert_eq!((tokens[0].start.line, tokens[0].start.column), (1, 1));
+        let streamed: Vec<Token> = lex_iter("\u{feff}a\n").collect::<Result<_, _>>().unwrap();
+        assert_eq!(streamed, lex("a\n").unwrap());
+        // Only the start of the source may hold one
+        assert!(lex("a\n\u{feff}b\n").is_err());
+        assert!(lex("\u{feff}\u{feff}a\n").is_err());
+    }
+
+    #[test]
+    fn test_clone_is_independent() {
+        let mut tokens = lex("name = b'\\x00' + 'text' + 1.5\n").unwrap();
+        let copy = tokens.clone();
+        assert_eq!(copy, tokens);
+
+        tokens.truncate(1);
+        tokens[0].token_type = TokenType::Name("other".to_owned());
+        assert_eq!(copy.len(), 8);
+        assert!(copy[0].is_type(&TokenType::Name("name".to_owned())));
+        assert!(copy[2].is_type(&TokenType::Bytes(vec![0])));
+        assert!(copy[4].is_type(&TokenType::Str("text".to_owned())));
+        assert!(copy[6].is_type(&TokenType::Float(1.5)));
+    }
+
+    /// Gives at most `size` bytes from each read, like a slow pipe
+    struct Trickle<R> {
+        inner: R,
+        size: usize,
+    }
+
+    impl<R: io::Read> io::Read for Trickle<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let size = self.size.min(buf.len());
+            self.inner.read(&mut buf[..size])
+        }
+    }
+
+    fn lex_trickled(source: &str, size: usize) -> Vec<Result<Token, LexError>> {
+        let reader = Trickle {
+            inner: io::Cursor::new(source.as_bytes().to_vec()),
+            size,
+        };
+        lex_reader(reader).collect()
+    }
+
+    #[test]
+    fn test_lex_reader_matches_lex() {
+        let sources = [
+            "",
+            "a = 1\n",
+            "def f(x):\n    if x:\n        return 'é' + \"\"\"a\nb\"\"\"\n    return 12345.5e3\n",
+            "x = [1,\n     2]  # comment\ny = 'ü' \\\n    'ß'\r\nz",
+            "\n\n  \nif a:\n    b\n",
+        ];
+        for source in sources.iter() {
+            let expected = lex(source).unwrap();
+            for size in [1, 2, 3, 7, 4096].iter() {
+                let tokens: Vec<Token> = lex_trickled(source, *size)
+                    .into_iter()
+                    .collect::<Result<_, _>>()
+                    .unwrap();
+                assert_eq!(tokens, expected, "{:?} in chunks of {}", source, size);
+            }
+        }
+    }
+
+    #[test]
+    fn test_lex_reader_errors() {
+        // The tokens before the error still come out, as with lex_iter
+        for size in [1, 5, 4096].iter() {
+            let results = lex_trickled("a\nb = 'open\nc\n", *size);
+            let streamed: Vec<_> = lex_iter("a\nb = 'open\nc\n").collect();
+            assert_eq!(format!("{:?}", results), format!("{:?}", streamed));
+            assert_eq!(results.len(), 5);
+            assert!(results[0]
+                .as_ref()
+                .unwrap()
+                .is_type(&TokenType::Name("a".to_owned())));
+            assert!(matches!(results[4], Err(LexError::UnterminatedString(_))));
+        }
+
+        let bytes = b"a\n\xff\n".to_vec();
+        let results: Vec<_> = lex_reader(io::Cursor::new(bytes)).collect();
+        assert!(matches!(results.last(), Some(Err(LexError::Io(_)))));
+    }
+
+    #[test]
+    fn test_string_kind() {
+        let tokens = lex("r\"x\" '''y''' \"z\" Rb'w' f\"{v}\" a").unwrap();
+        let kinds: Vec<Option<StringKind>> =
+            tokens.iter().map(|token| token.string_kind()).collect();
+        let prefix = |raw, bytes, format| StringPrefix { raw, bytes, format };
+        assert_eq!(
+            kinds,
+            vec![
+                Some(StringKind {
+                    quote: '"',
+                    triple: false,
+                    prefix: prefix(true, false, false),
+                }),
+                Some(StringKind {
+                    quote: '\'',
+                    triple: true,
+                    prefix: StringPrefix::default(),
+                }),
+                Some(StringKind {
+                    quote: '"',
+                    triple: false,
+                    prefix: StringPrefix::default(),
+                }),
+                Some(StringKind {
+                    quote: '\'',
+                    triple: false,
+                    prefix: prefix(true, true, false),
+                }),
+                Some(StringKind {
+                    quote: '"',
+                    triple: false,
+                    prefix: prefix(false, false, true),
+                }),
+                Option::None,
+            ]
+        );
+        // The empty string is not mistaken for a triple quote
+        let kind = lex("''").unwrap()[0].string_kind().unwrap();
+        assert!(!kind.triple);
+    }
+
+    #[test]
+    fn test_token_kind_order() {
+        let mut kinds = vec![
+            TokenKind::Newline,
+            TokenKind::If,
+            TokenKind::Name,
+            TokenKind::Comma,
+            TokenKind::LeftParen,
+            TokenKind::Equal,
+            TokenKind::Less,
+            TokenKind::Amp,
+            TokenKind::Plus,
+        ];
+        kinds.sort();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::Plus,
+                TokenKind::Amp,
+                TokenKind::Less,
+                TokenKind::Equal,
+                TokenKind::LeftParen,
+                TokenKind::Comma,
+                TokenKind::Name,
+                TokenKind::If,
+                TokenKind::Newline,
+            ]
+        );
+
+        // Every kind follows the declaration order of TokenType
+        let types = every_token_type();
+        for pair in types.windows(2) {
+            assert!(pair[0].kind() < pair[1].kind(), "{:?}", pair);
+            assert!(
+                variant_index(&pair[0]) < variant_index(&pair[1]),
+                "{:?}",
+                pair
+            );
+        }
+        assert_eq!(
+            TokenType::Name("b".to_owned()).kind(),
+            TokenType::Name("a".to_owned()).kind()
+        );
+        assert_eq!(TokenType::Float(f64::NAN).kind(), TokenKind::Float);
     }
 }
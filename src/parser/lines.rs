@@ -0,0 +1,317 @@
+//! The lines module holds functions that group
+//! a lexed token stream by line
+
+use super::lexer::{lex, LexError, Location, Token, TokenCategory, TokenType};
+use std::ops::Range;
+
+/// One physical line of the source, for line oriented passes such as formatting
+#[derive(Debug)]
+pub struct PhysicalLine<'a> {
+    /// The line number, starting at 1
+    pub number: u64,
+    /// The tokens that start on this line, without any layout tokens
+    pub tokens: &'a [Token],
+    /// The comment at the end of the line, starting at the `#`
+    pub comment: Option<&'a str>,
+}
+
+/// Split a token stream into logical lines.
+///
+/// Each group ends with the `Newline` token that closed it. Physical lines
+/// joined inside brackets never contain a `Newline`, so they stay in one group.
+/// `Indent` and `Dedent` tokens follow a `Newline` and so start the next group;
+/// any left at the very end of the stream are attached to the last group.
+///
+/// ```
+/// extern crate oxy_python;
+/// use oxy_python::parser::{lex, logical_lines};
+/// let lines: Vec<_> = logical_lines(lex("a\nb").unwrap()).collect();
+/// assert_eq!(lines.len(), 2);
+/// ```
+pub fn logical_lines(tokens: Vec<Token>) -> impl Iterator<Item = Vec<Token>> {
+    let mut lines: Vec<Vec<Token>> = Vec::new();
+    let mut current = Vec::new();
+
+    for token in tokens {
+        let ends_line = token.is_type(&TokenType::Newline);
+        current.push(token);
+        if ends_line {
+            lines.push(std::mem::take(&mut current));
+        }
+    }
+
+    if !current.is_empty() {
+        let only_layout = current
+            .iter()
+            .all(|t| t.is_type(&TokenType::Indent) || t.is_type(&TokenType::Dedent));
+        match lines.last_mut() {
+            Some(last) if only_layout => last.extend(current),
+            _ => lines.push(current),
+        }
+    }
+
+    lines.into_iter()
+}
+
+/// Find the token index range of each top level statement, without parsing.
+///
+/// A statement starts at the first token of a logical line that is not
+/// indented, and runs up to the start of the next one, so the body of a
+/// compound statement and its closing `Dedent` tokens belong to it.
+/// Statements joined by `;` on one line count as one.
+///
+/// ```
+/// extern crate oxy_python;
+/// use oxy_python::parser::{lex, top_level_statement_boundaries};
+/// let tokens = lex("if a:\n    b\nc").unwrap();
+/// assert_eq!(top_level_statement_boundaries(&tokens), vec![0..8, 8..9]);
+/// ```
+pub fn top_level_statement_boundaries(tokens: &[Token]) -> Vec<Range<usize>> {
+    let mut starts = Vec::new();
+    let mut depth = 0;
+    let mut at_line_start = true;
+
+    for (index, token) in tokens.iter().enumerate() {
+        match token.token_type() {
+            TokenType::Indent => depth += 1,
+            TokenType::Dedent => depth -= 1,
+            TokenType::Newline => at_line_start = true,
+            TokenType::Nl => {}
+            _ => {
+                if at_line_start && depth == 0 {
+                    starts.push(index);
+                }
+                at_line_start = false;
+            }
+        }
+    }
+
+    let ends = starts.iter().skip(1).copied().chain(Some(tokens.len()));
+    starts
+        .iter()
+        .zip(ends)
+        .map(|(&start, end)| start..end)
+        .collect()
+}
+
+/// Walk the physical lines of the source along with the tokens lexed from it.
+///
+/// Every line is reported, including blank lines and lines that continue a
+/// logical line inside brackets. A token that spans several lines, such as a
+/// triple quoted string, belongs to the line it starts on; the lines it covers
+/// have no comment.
+/// The tokens must count lines and columns from 1, as `lex` gives them.
+///
+/// ```
+/// extern crate oxy_python;
+/// use oxy_python::parser::{lex, lines_iter};
+/// let source = "a = 1  # one\nb = 2";
+/// let tokens = lex(source).unwrap();
+/// let lines: Vec<_> = lines_iter(source, &tokens).collect();
+/// assert_eq!(lines[0].comment, Some("# one"));
+/// assert_eq!(lines[1].tokens.len(), 3);
+/// ```
+pub fn lines_iter<'a>(
+    source: &'a str,
+    tokens: &'a [Token],
+) -> impl Iterator<Item = PhysicalLine<'a>> + 'a {
+    let mut next = 0;
+    // The end of the last token with text, comments can only come after it
+    let mut covered = Location::new(0, 0);
+
+    source.lines().enumerate().map(move |(index, text)| {
+        let number = index as u64 + 1;
+        let start = next;
+        while next < tokens.len() && tokens[next].start().line() <= number {
+            let token = &tokens[next];
+            if token.token_type().category() != TokenCategory::Layout {
+                covered = covered.max(*token.end());
+            }
+            next += 1;
+        }
+
+        let mut line_tokens = &tokens[start..next];
+        while let Some((first, rest)) = line_tokens.split_first() {
+            if first.token_type().category() != TokenCategory::Layout {
+                break;
+            }
+            line_tokens = rest;
+        }
+        while let Some((last, rest)) = line_tokens.split_last() {
+            if last.token_type().category() != TokenCategory::Layout {
+                break;
+            }
+            line_tokens = rest;
+        }
+
+        let comment = if covered.line() > number {
+            None
+        } else {
+            let skip = if covered.line() == number {
+                covered.column() as usize
+            } else {
+                0
+            };
+            let offset = text.char_indices().nth(skip).map_or(text.len(), |(i, _)| i);
+            text[offset..].find('#').map(|i| &text[offset + i..])
+        };
+
+        PhysicalLine {
+            number,
+            tokens: line_tokens,
+            comment,
+        }
+    })
+}
+
+/// Line counts of the source, showing how much the logical lines compress
+/// the physical ones
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LineMetrics {
+    /// The number of lines in the source
+    pub physical: u64,
+    /// The number of logical lines, ended by `Newline` or by the end of the source
+    pub logical: u64,
+    /// The number of lines with only whitespace
+    pub blank: u64,
+    /// The number of lines with only a comment
+    pub comment: u64,
+}
+
+/// Count the physical, logical, blank and comment only lines of the source.
+///
+/// Lines covered by a token that spans several lines, such as a triple quoted
+/// string, count as code even when they look blank or start with `#`.
+///
+/// ```
+/// extern crate oxy_python;
+/// use oxy_python::parser::line_metrics;
+/// let metrics = line_metrics("x = (1,\n     2)\n\n# done\n").unwrap();
+/// assert_eq!((metrics.physical, metrics.logical), (4, 1));
+/// assert_eq!((metrics.blank, metrics.comment), (1, 1));
+/// ```
+pub fn line_metrics(source: &str) -> Result<LineMetrics, LexError> {
+    let tokens = lex(source)?;
+    let text_lines: Vec<&str> = source.lines().collect();
+    let mut metrics = LineMetrics {
+        physical: text_lines.len() as u64,
+        ..LineMetrics::default()
+    };
+
+    // The lines that hold part of a token, indexed from 1
+    let mut code = vec![false; text_lines.len() + 2];
+    let mut open_line = false;
+    for token in &tokens {
+        if token.is_type(&TokenType::Newline) {
+            metrics.logical += 1;
+            open_line = false;
+        } else if token.token_type().category() != TokenCategory::Layout {
+            open_line = true;
+            for line in token.start().line()..=token.end().line() {
+                code[line as usize] = true;
+            }
+        }
+    }
+    // The last line does not need a line break to be a logical line
+    if open_line {
+        metrics.logical += 1;
+    }
+
+    for (index, text) in text_lines.iter().enumerate() {
+        if code[index + 1] {
+            continue;
+        }
+        let text = text.trim_start();
+        if text.is_empty() {
+            metrics.blank += 1;
+        } else if text.starts_with('#') {
+            metrics.comment += 1;
+        }
+    }
+
+    Ok(metrics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn types(line: &[Token]) -> Vec<TokenType> {
+        line.iter().map(|t| t.token_type().clone()).collect()
+    }
+
+    #[test]
+    fn test_two_statements() {
+        use super::TokenType::*;
+        let lines: Vec<Vec<Token>> = logical_lines(lex("a + b\nc").unwrap()).collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            types(&lines[0]),
+            vec![Name("a".to_owned()), Plus, Name("b".to_owned()), Newline]
+        );
+        assert_eq!(types(&lines[1]), vec![Name("c".to_owned())]);
+    }
+
+    #[test]
+    fn test_bracket_continuation() {
+        use super::TokenType::*;
+        let lines: Vec<Vec<Token>> = logical_lines(lex("(a\n+ b)\nc\n").unwrap()).collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].len(), 6);
+        assert_eq!(types(&lines[1]), vec![Name("c".to_owned()), Newline]);
+    }
+
+    #[test]
+    fn test_empty() {
+        assert_eq!(logical_lines(Vec::new()).count(), 0);
+        assert!(top_level_statement_boundaries(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_lines_iter() {
+        let source =
+            "x = [1,  # first\n     2]\n\n# alone\ns = '#'  # after\nt = \"\"\"a\n# b\"\"\"\n";
+        let tokens = lex(source).unwrap();
+        let lines: Vec<PhysicalLine> = lines_iter(source, &tokens).collect();
+        assert_eq!(lines.len(), 7);
+        assert_eq!(types(lines[0].tokens).len(), 5);
+        assert_eq!(lines[0].comment, Some("# first"));
+        assert_eq!(
+            types(lines[1].tokens),
+            vec![TokenType::Int(2), TokenType::RightBracket]
+        );
+        assert_eq!(lines[1].comment, None);
+        assert!(lines[2].tokens.is_empty());
+        assert_eq!(lines[3].comment, Some("# alone"));
+        assert_eq!(lines[4].comment, Some("# after"));
+        assert_eq!(lines[5].tokens.len(), 3);
+        assert_eq!(lines[6].comment, None);
+    }
+
+    #[test]
+    fn test_top_level_statements() {
+        let source = "import a\n\ndef f(x):\n    if x:\n        return 1\n    return 2\n\nf(a)\n";
+        let tokens = lex(source).unwrap();
+        let ranges = top_level_statement_boundaries(&tokens);
+        assert_eq!(ranges.len(), 3);
+        assert!(tokens[ranges[0].start].is_type(&TokenType::Import));
+        assert!(tokens[ranges[1].start].is_type(&TokenType::Def));
+        assert!(tokens[ranges[1].end - 1].is_type(&TokenType::Dedent));
+        assert_eq!(ranges[2].end, tokens.len());
+    }
+
+    #[test]
+    fn test_line_metrics() {
+        let source = "total = 1 + \\\n    2\nvalues = [\n    1,\n    2,\n]\n\n  # note\ns = \"\"\"\n\n# kept\"\"\"\n";
+        let metrics = line_metrics(source).unwrap();
+        assert_eq!(metrics.physical, 11);
+        assert_eq!(metrics.logical, 3);
+        assert_eq!(metrics.blank, 1);
+        assert_eq!(metrics.comment, 1);
+        assert!(metrics.logical < metrics.physical);
+
+        assert_eq!(line_metrics("").unwrap(), LineMetrics::default());
+        assert_eq!(line_metrics("a").unwrap().logical, 1);
+        assert_eq!(line_metrics("a\n\n").unwrap().blank, 1);
+    }
+}
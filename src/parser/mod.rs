@@ -1,5 +1,37 @@
 //! Parser functions
 
+mod brackets;
+#[cfg(feature = "cache")]
+mod cache;
+mod dedent;
+mod diff;
+mod fingerprint;
+mod format;
+mod highlight;
+mod json;
 mod lexer;
+mod lines;
+mod query;
+mod repl;
+mod source_map;
+mod span;
+mod strings;
+mod tokenize;
 
+pub use brackets::*;
+#[cfg(feature = "cache")]
+pub use cache::*;
+pub use dedent::*;
+pub use diff::*;
+pub use fingerprint::*;
+pub use format::*;
+pub use highlight::*;
+pub use json::*;
 pub use lexer::*;
+pub use lines::*;
+pub use query::*;
+pub use repl::*;
+pub use source_map::*;
+pub use span::*;
+pub use strings::*;
+pub use tokenize::*;
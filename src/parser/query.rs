@@ -0,0 +1,153 @@
+//! The query module finds tokens by their
+//! position in the file
+
+use super::lexer::{Location, Token, TokenCategory};
+
+/// The nearest token that starts before the cursor.
+///
+/// The cursor sits in front of the character at `line` and `column`, so a
+/// cursor between two tokens finds the one on its left, and a cursor inside a
+/// token finds that token. Layout tokens are skipped as they have no text.
+pub fn token_before(tokens: &[Token], line: u64, column: u64) -> Option<&Token> {
+    let cursor = Location::new(line, column);
+    tokens
+        .iter()
+        .rev()
+        .filter(|token| has_text(token))
+        .find(|token| *token.start() < cursor)
+}
+
+/// The nearest token that ends at or after the cursor.
+///
+/// The cursor sits in front of the character at `line` and `column`, so a
+/// cursor between two tokens finds the one on its right, and a cursor inside a
+/// token finds that token. Layout tokens are skipped as they have no text.
+pub fn token_after(tokens: &[Token], line: u64, column: u64) -> Option<&Token> {
+    let cursor = Location::new(line, column);
+    tokens
+        .iter()
+        .filter(|token| has_text(token))
+        .find(|token| *token.end() >= cursor)
+}
+
+/// The token that covers the byte at `offset` in the source.
+///
+/// Editors often have a byte offset rather than a line and column. The tokens
+/// must be lexed with `LexerOptions::byte_ranges`, tokens without a byte range
+/// are never found. Layout tokens are skipped, so an offset in whitespace,
+/// a comment or a line break has no token.
+///
+/// ```
+/// extern crate oxy_python;
+/// use oxy_python::parser::{lex_with_options, token_at_byte, LexerOptions, TokenType};
+/// let options = LexerOptions {
+///     byte_ranges: true,
+///     ..LexerOptions::default()
+/// };
+/// let tokens = lex_with_options("a = bc", &options).unwrap();
+/// let token = token_at_byte(&tokens, 5).unwrap();
+/// assert_eq!(token.token_type(), &TokenType::Name("bc".to_owned()));
+/// ```
+pub fn token_at_byte(tokens: &[Token], offset: usize) -> Option<&Token> {
+    tokens.iter().filter(|token| has_text(token)).find(|token| {
+        token
+            .byte_range()
+            .is_some_and(|range| range.contains(&offset))
+    })
+}
+
+/// Asserts if `b` starts directly after `a` ends, with nothing between them.
+///
+/// Both tokens must be on the same line, so `a.b` is adjacent and `a . b` is not.
+pub fn are_adjacent(a: &Token, b: &Token) -> bool {
+    a.end().line() == b.start().line() && a.end().column() + 1 == b.start().column()
+}
+
+fn has_text(token: &Token) -> bool {
+    token.token_type().category() != TokenCategory::Layout
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{lex, lex_with_options, LexerOptions, TokenType};
+
+    fn name(token: Option<&Token>) -> Option<&TokenType> {
+        token.map(|t| t.token_type())
+    }
+
+    #[test]
+    fn test_boundary() {
+        let tokens = lex("ab+cd").unwrap();
+        assert_eq!(
+            name(token_before(&tokens, 1, 3)),
+            Some(&TokenType::Name("ab".to_owned()))
+        );
+        assert_eq!(name(token_after(&tokens, 1, 3)), Some(&TokenType::Plus));
+        assert_eq!(name(token_before(&tokens, 1, 4)), Some(&TokenType::Plus));
+        assert_eq!(
+            name(token_after(&tokens, 1, 4)),
+            Some(&TokenType::Name("cd".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_inside_token() {
+        let tokens = lex("abc d").unwrap();
+        let abc = Some(&TokenType::Name("abc".to_owned()));
+        assert_eq!(name(token_before(&tokens, 1, 2)), abc);
+        assert_eq!(name(token_after(&tokens, 1, 2)), abc);
+    }
+
+    #[test]
+    fn test_are_adjacent() {
+        let tokens = lex("ab.cd").unwrap();
+        assert!(are_adjacent(&tokens[0], &tokens[1]));
+        assert!(are_adjacent(&tokens[1], &tokens[2]));
+        assert!(!are_adjacent(&tokens[0], &tokens[2]));
+
+        let tokens = lex("ab . cd").unwrap();
+        assert!(!are_adjacent(&tokens[0], &tokens[1]));
+        assert!(!are_adjacent(&tokens[1], &tokens[2]));
+
+        let tokens = lex("(a\n).b").unwrap();
+        assert!(!are_adjacent(&tokens[1], &tokens[2]));
+        assert!(are_adjacent(&tokens[2], &tokens[3]));
+    }
+
+    #[test]
+    fn test_across_lines() {
+        let tokens = lex("if a:\n    b\n").unwrap();
+        assert_eq!(name(token_before(&tokens, 2, 3)), Some(&TokenType::Colon));
+        assert_eq!(
+            name(token_after(&tokens, 2, 3)),
+            Some(&TokenType::Name("b".to_owned()))
+        );
+        assert_eq!(name(token_before(&tokens, 1, 1)), None);
+        assert_eq!(name(token_after(&tokens, 3, 1)), None);
+    }
+
+    #[test]
+    fn test_token_at_byte() {
+        let options = LexerOptions {
+            byte_ranges: true,
+            ..LexerOptions::default()
+        };
+        let source = "a = 1\nif b:\n    cé = 'x y'  # note\n";
+        let tokens = lex_with_options(source, &options).unwrap();
+        let at = |needle: &str| name(token_at_byte(&tokens, source.find(needle).unwrap()));
+
+        assert_eq!(at("if"), Some(&TokenType::If));
+        assert_eq!(at("f b"), Some(&TokenType::If));
+        assert_eq!(at("é"), Some(&TokenType::Name("cé".to_owned())));
+        assert_eq!(at(" = '"), None);
+        assert_eq!(at("= '"), Some(&TokenType::Equal));
+        assert_eq!(at("y'"), Some(&TokenType::Str("x y".to_owned())));
+        assert_eq!(at("note"), None);
+        assert_eq!(name(token_at_byte(&tokens, source.len())), None);
+
+        // Without byte ranges nothing is found
+        let tokens = lex(source).unwrap();
+        assert_eq!(name(token_at_byte(&tokens, 0)), None);
+    }
+}
@@ -0,0 +1,235 @@
+//! The repl module holds functions that help an
+//! interactive prompt decide how to continue a block or a line
+
+use super::lexer::{lex, TokenCategory, TokenType};
+use super::source_map::SourceMap;
+
+/// Checks if the last logical line of the source opens a block by ending in `:`.
+///
+/// Source that does not lex, such as an unclosed triple quoted string, has no
+/// pending block.
+///
+/// ```
+/// extern crate oxy_python;
+/// use oxy_python::parser::pending_block;
+/// assert!(pending_block("def f():\n"));
+/// assert!(!pending_block("x = {1: 2}\n"));
+/// ```
+pub fn pending_block(source: &str) -> bool {
+    let tokens = match lex(source) {
+        Ok(tokens) => tokens,
+        Err(_) => return false,
+    };
+    let last = tokens
+        .iter()
+        .rev()
+        .find(|token| token.token_type().category() != TokenCategory::Layout);
+    match last {
+        Some(token) => token.is_type(&TokenType::Colon),
+        None => false,
+    }
+}
+
+/// The indentation width of the last logical line of the source.
+///
+/// Tabs count up to the next multiple of 8, as they do in the lexer.
+/// Blank and comment only lines are skipped, and source that does not lex
+/// has no indentation.
+///
+/// ```
+/// extern crate oxy_python;
+/// use oxy_python::parser::current_indent;
+/// assert_eq!(current_indent("if a:\n    b\n"), 4);
+/// ```
+pub fn current_indent(source: &str) -> u64 {
+    let tokens = match lex(source) {
+        Ok(tokens) => tokens,
+        Err(_) => return 0,
+    };
+
+    // The first token of the last logical line
+    let mut line_start = None;
+    let mut at_line_start = true;
+    for token in &tokens {
+        match token.token_type().category() {
+            _ if token.is_type(&TokenType::Newline) => at_line_start = true,
+            TokenCategory::Layout => {}
+            _ if at_line_start => {
+                line_start = Some(token.start().line());
+                at_line_start = false;
+            }
+            _ => {}
+        }
+    }
+
+    let text = match line_start.and_then(|line| source.lines().nth(line as usize - 1)) {
+        Some(text) => text,
+        None => return 0,
+    };
+    let mut width = 0;
+    for c in text.chars() {
+        match c {
+            ' ' => width += 1,
+            '\t' => width += 8 - width % 8,
+            '\x0c' => width = 0,
+            _ => break,
+        }
+    }
+    width
+}
+
+/// Checks if the last logical line of the source is unfinished, so the next
+/// line continues it.
+///
+/// That is when its last line ends in a backslash outside any comment or
+/// string, or a bracket is still open. Source that does not lex does not
+/// continue, so its error can be shown.
+///
+/// ```
+/// extern crate oxy_python;
+/// use oxy_python::parser::ends_with_continuation;
+/// assert!(ends_with_continuation("a + \\\n"));
+/// assert!(ends_with_continuation("x = (1,\n"));
+/// assert!(!ends_with_continuation("a +  # \\\n"));
+/// ```
+pub fn ends_with_continuation(source: &str) -> bool {
+    // A backslash at the very end only continues once its line is ended
+    let ended;
+    let source = if source.ends_with('\n') {
+        source
+    } else {
+        ended = format!("{}\n", source);
+        &ended
+    };
+    let tokens = match lex(source) {
+        Ok(tokens) => tokens,
+        Err(_) => return false,
+    };
+    // A finished logical line ends in a `Newline`, the lexer gives none for
+    // one that is cut off
+    let last = tokens.iter().rev().find(|token| {
+        !matches!(
+            token.token_type(),
+            TokenType::Indent | TokenType::Dedent | TokenType::Nl
+        )
+    });
+    last.is_some_and(|token| !token.is_type(&TokenType::Newline))
+}
+
+/// Joins the lines of a buffer that end in a backslash onto the line after them.
+///
+/// The backslash and the line break after it are removed, as the lexer would
+/// do, so the buffer can be shown or lexed as whole logical lines. Backslashes
+/// in comments and strings are kept, and a buffer that does not lex is
+/// returned as it is.
+///
+/// ```
+/// extern crate oxy_python;
+/// use oxy_python::parser::join_continued_lines;
+/// assert_eq!(join_continued_lines("a + \\\n  b\n"), "a +   b\n");
+/// ```
+pub fn join_continued_lines(buffer: &str) -> String {
+    let tokens = match lex(buffer) {
+        Ok(tokens) => tokens,
+        Err(_) => return buffer.to_owned(),
+    };
+    let source_map = SourceMap::new(buffer);
+
+    let mut result = String::with_capacity(buffer.len());
+    let mut cursor = 0;
+    for token in &tokens {
+        if token.string_kind().is_none() {
+            continue;
+        }
+        // Strings are copied as they are, a backslash in one is part of its value
+        let range = source_map.span_to_byte_range(&token.span());
+        join_gap(&buffer[cursor..range.start], &mut result);
+        result.push_str(&buffer[range.clone()]);
+        cursor = range.end;
+    }
+    join_gap(&buffer[cursor..], &mut result);
+    result
+}
+
+/// Copy source between strings, dropping each backslash continuation outside comments
+fn join_gap(gap: &str, result: &mut String) {
+    let mut in_comment = false;
+    let mut rest = gap;
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '#' => in_comment = true,
+            '\n' => in_comment = false,
+            '\\' if !in_comment => {
+                if let Some(after) = rest[1..]
+                    .strip_prefix("\r\n")
+                    .or_else(|| rest[1..].strip_prefix('\n'))
+                {
+                    rest = after;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        result.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pending_block() {
+        assert!(pending_block("def f():"));
+        assert!(pending_block("def f():\n"));
+        assert!(pending_block("if a:\n    for b in c:  # loop\n"));
+        assert!(!pending_block("def f(): return 1\n"));
+        assert!(!pending_block("a[1:]\n"));
+        assert!(!pending_block(""));
+        assert!(!pending_block("'''\nif a:"));
+    }
+
+    #[test]
+    fn test_current_indent() {
+        assert_eq!(current_indent(""), 0);
+        assert_eq!(current_indent("a\n"), 0);
+        assert_eq!(current_indent("def f():\n    if a:\n        b\n\n"), 8);
+        assert_eq!(current_indent("if a:\n\tb\n    # comment\n"), 8);
+        assert_eq!(current_indent("if a:\n  b = (1,\n2)\n"), 2);
+    }
+
+    #[test]
+    fn test_ends_with_continuation() {
+        assert!(ends_with_continuation("a +\\"));
+        assert!(ends_with_continuation("a +\\\r\n"));
+        assert!(!ends_with_continuation("a +"));
+        assert!(!ends_with_continuation("a \\ b"));
+        assert!(!ends_with_continuation(""));
+        // Backslashes in comments and strings do not continue the line
+        assert!(!ends_with_continuation("x = 1  # C:\\\n"));
+        assert!(!ends_with_continuation("x = 'C:\\\\'\n"));
+        // Open brackets do, through blank lines and comments
+        assert!(ends_with_continuation("x = (1,\n"));
+        assert!(ends_with_continuation("x = [1,\n\n  # two\n"));
+        assert!(!ends_with_continuation("x = [1,\n  2]\n"));
+        assert!(ends_with_continuation("if a:\n    b = {\n"));
+        assert!(!ends_with_continuation("if a:\n    b\n"));
+    }
+
+    #[test]
+    fn test_join_continued_lines() {
+        let joined = join_continued_lines("a +\\\nb");
+        assert_eq!(joined, "a +b");
+        assert_eq!(joined.lines().count(), 1);
+        assert_eq!(
+            join_continued_lines("a = 1 + \\\r\n    2\nb\n"),
+            "a = 1 +     2\nb\n"
+        );
+        assert_eq!(join_continued_lines("a\nb\n"), "a\nb\n");
+        assert_eq!(
+            join_continued_lines("s = '''a\\\nb''' + \\\n  c  # d\\\n"),
+            "s = '''a\\\nb''' +   c  # d\\\n"
+        );
+    }
+}
@@ -0,0 +1,194 @@
+//! The source map module converts the line and column
+//! locations used by tokens into byte offsets
+
+use std::ops::Range;
+
+use super::lexer::{Location, BOM};
+use super::span::Span;
+
+/// Maps locations in a source string to byte offsets
+#[derive(Debug)]
+pub struct SourceMap<'a> {
+    /// The source being mapped
+    source: &'a str,
+    /// The byte offset each line starts at, the first line is at index 0
+    line_starts: Vec<usize>,
+    /// The number of the first line and column of the locations mapped, 1 or 0
+    base: u64,
+}
+
+impl<'a> SourceMap<'a> {
+    /// Build a source map over the given source.
+    ///
+    /// A byte order mark at the start is not part of the first line, as the
+    /// lexer skips it. Locations count from 1, as the lexer gives them by default.
+    pub fn new(source: &'a str) -> SourceMap<'a> {
+        let first = if source.starts_with(BOM) {
+            BOM.len_utf8()
+        } else {
+            0
+        };
+        let mut line_starts = vec![first];
+        line_starts.extend(source.match_indices('\n').map(|(index, _)| index + 1));
+        SourceMap {
+            source,
+            line_starts,
+            base: 1,
+        }
+    }
+
+    /// Build a source map for locations that count from 0, as the lexer gives
+    /// them with `LexerOptions::zero_based_positions`
+    pub fn zero_based(source: &'a str) -> SourceMap<'a> {
+        SourceMap {
+            base: 0,
+            ..SourceMap::new(source)
+        }
+    }
+
+    /// The byte offset of the character at the location.
+    ///
+    /// Columns count characters, so multi-byte characters are handled.
+    /// A location past the end of its line maps to the end of the line,
+    /// and a line past the end of the source maps to the end of the source.
+    /// A location before the base of the map, such as line 0 in a map that
+    /// counts from 1, maps to the start of its line or of the source.
+    pub fn byte_offset(&self, location: &Location) -> usize {
+        let line_index = match location.line().checked_sub(self.base) {
+            Some(index) => index as usize,
+            None => return 0,
+        };
+        let line_start = match self.line_starts.get(line_index) {
+            Some(start) => *start,
+            None => return self.source.len(),
+        };
+        let line = &self.source[line_start..];
+        let line_len = line.find('\n').unwrap_or(line.len());
+        let column_index = location.column().saturating_sub(self.base) as usize;
+
+        match line.char_indices().nth(column_index) {
+            Some((index, _)) if index <= line_len => line_start + index,
+            _ => line_start + line_len,
+        }
+    }
+
+    /// The byte offset just past the character at the location
+    pub fn byte_offset_after(&self, location: &Location) -> usize {
+        let offset = self.byte_offset(location);
+        match self.source[offset..].chars().next() {
+            Some(c) => offset + c.len_utf8(),
+            None => offset,
+        }
+    }
+
+    /// The byte range covered by the span, ending just past its last character
+    pub fn span_to_byte_range(&self, span: &Span) -> Range<usize> {
+        self.byte_offset(span.start())..self.byte_offset_after(span.end())
+    }
+
+    /// The number of lines in the source.
+    ///
+    /// A final line break ends the last line rather than starting a new one,
+    /// so `"a\nb\n"` has 2 lines and an empty source has none.
+    pub fn line_count(&self) -> u64 {
+        let starts = self.line_starts.len() as u64;
+        if self.source.is_empty() || self.source.ends_with('\n') {
+            starts - 1
+        } else {
+            starts
+        }
+    }
+
+    /// Asserts if the line, counting from 1, is empty or only whitespace.
+    ///
+    /// Lines past the end of the source are not blank, as they do not exist.
+    pub fn is_blank(&self, line: u64) -> bool {
+        if line == 0 || line > self.line_count() {
+            return false;
+        }
+        let start = self.line_starts[line as usize - 1];
+        let end = self
+            .line_starts
+            .get(line as usize)
+            .map_or(self.source.len(), |next| next - 1);
+        self.source[start..end]
+            .chars()
+            .all(|c| matches!(c, ' ' | '\t' | '\r' | '\x0c'))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{lex, lex_with_options, LexerOptions, TokenCategory};
+
+    #[test]
+    fn test_byte_offset() {
+        let source = "ab\né c\n";
+        let map = SourceMap::new(source);
+        let tokens = lex(source).unwrap();
+        assert_eq!(map.byte_offset(tokens[0].start()), 0);
+        assert_eq!(map.byte_offset_after(tokens[0].end()), 2);
+        assert_eq!(map.byte_offset(tokens[2].start()), 3);
+        assert_eq!(map.byte_offset_after(tokens[2].end()), 5);
+        assert_eq!(map.byte_offset(tokens[3].start()), 6);
+    }
+
+    #[test]
+    fn test_line_count_and_blank_lines() {
+        let map = SourceMap::new("a = 1\n\n  \t\nb\r\n \r\n");
+        assert_eq!(map.line_count(), 5);
+        let blank: Vec<bool> = (0..=6).map(|line| map.is_blank(line)).collect();
+        assert_eq!(blank, vec![false, false, true, true, false, true, false]);
+
+        assert_eq!(SourceMap::new("").line_count(), 0);
+        assert_eq!(SourceMap::new("a").line_count(), 1);
+        assert_eq!(SourceMap::new("\n").line_count(), 1);
+        assert!(SourceMap::new("a\n ").is_blank(2));
+    }
+
+    #[test]
+    fn test_span_to_byte_range() {
+        let source = "'\u{1d4b3}' ab\n    c";
+        let map = SourceMap::new(source);
+        let tokens = lex(source).unwrap();
+
+        let range = map.span_to_byte_range(&tokens[0].span());
+        assert_eq!(range, 0..6);
+        let range = map.span_to_byte_range(&tokens[1].span());
+        assert_eq!(&source[range.clone()], "ab");
+        assert_eq!(range, 7..9);
+        let range = map.span_to_byte_range(&tokens[4].span());
+        assert_eq!(&source[range], "c");
+    }
+
+    #[test]
+    fn test_byte_order_mark() {
+        let source = "\u{feff}x = 'é'\n";
+        let map = SourceMap::new(source);
+        let tokens = lex(source).unwrap();
+        assert_eq!(map.byte_offset(tokens[0].start()), 3);
+        assert_eq!(&source[map.span_to_byte_range(&tokens[2].span())], "'é'");
+    }
+
+    #[test]
+    fn test_zero_based() {
+        let source = "ab\né c";
+        let options = LexerOptions {
+            zero_based_positions: true,
+            ..LexerOptions::default()
+        };
+        let tokens = lex_with_options(source, &options).unwrap();
+        let map = SourceMap::zero_based(source);
+        let texts: Vec<&str> = tokens
+            .iter()
+            .filter(|token| token.token_type().category() != TokenCategory::Layout)
+            .map(|token| &source[map.span_to_byte_range(&token.span())])
+            .collect();
+        assert_eq!(texts, vec!["ab", "é", "c"]);
+
+        // Locations before the start of a map counting from 1 do not underflow
+        let map = SourceMap::new(source);
+        assert_eq!(map.span_to_byte_range(&tokens[0].span()), 0..1);
+    }
+}
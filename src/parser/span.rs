@@ -0,0 +1,124 @@
+//! The span module holds the range of source
+//! covered by a token or a group of tokens
+
+use std::ops::Deref;
+
+use super::lexer::{Location, Token};
+
+/// A range in the file, from the start of one character to the end of another
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    /// The location of the first character
+    start: Location,
+    /// The location of the last character
+    end: Location,
+}
+
+impl Span {
+    /// Create a span covering `start` to `end`
+    pub fn new(start: Location, end: Location) -> Span {
+        Span { start, end }
+    }
+
+    /// The location of the first character
+    pub fn start(&self) -> &Location {
+        &self.start
+    }
+
+    /// The location of the last character
+    pub fn end(&self) -> &Location {
+        &self.end
+    }
+}
+
+impl Token {
+    /// The span covered by this token
+    pub fn span(&self) -> Span {
+        Span::new(*self.start(), *self.end())
+    }
+}
+
+/// A value together with the span of source it came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Spanned<T> {
+    /// The value
+    pub node: T,
+    /// The span the value covers
+    pub span: Span,
+}
+
+impl<T> Spanned<T> {
+    /// Attach a span to the value
+    pub fn new(node: T, span: Span) -> Spanned<T> {
+        Spanned { node, span }
+    }
+
+    /// Change the value, keeping the span
+    pub fn map<U, F>(self, f: F) -> Spanned<U>
+    where
+        F: FnOnce(T) -> U,
+    {
+        Spanned::new(f(self.node), self.span)
+    }
+
+    /// Borrow the value, keeping the span
+    pub fn as_ref(&self) -> Spanned<&T> {
+        Spanned::new(&self.node, self.span)
+    }
+}
+
+impl<T> Deref for Spanned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.node
+    }
+}
+
+/// The span from the start of the first token to the end of the last one.
+///
+/// Returns `None` for an empty slice.
+pub fn span_of(tokens: &[Token]) -> Option<Span> {
+    let first = tokens.first()?;
+    let last = tokens.last()?;
+    Some(Span::new(*first.start(), *last.end()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::lex;
+
+    #[test]
+    fn test_span_of() {
+        let tokens = lex("a + (b\n  )").unwrap();
+        let span = span_of(&tokens[2..5]).unwrap();
+        assert_eq!((span.start().line(), span.start().column()), (1, 5));
+        assert_eq!((span.end().line(), span.end().column()), (2, 3));
+
+        let span = span_of(&tokens[..1]).unwrap();
+        assert_eq!(span, tokens[0].span());
+    }
+
+    #[test]
+    fn test_spanned() {
+        let tokens = lex("abc").unwrap();
+        let name = Spanned::new("abc".to_owned(), tokens[0].span());
+        assert_eq!(name.len(), 3);
+        assert!(name.starts_with("ab"));
+
+        let borrowed = name.as_ref();
+        assert_eq!(*borrowed.node, "abc");
+        assert_eq!(borrowed.span, name.span);
+
+        let length = name.clone().map(|s| s.len());
+        assert_eq!(length.node, 3);
+        assert_eq!(length.span, name.span);
+        assert_eq!(*length + 1, 4);
+    }
+
+    #[test]
+    fn test_span_of_empty() {
+        assert_eq!(span_of(&[]), None);
+    }
+}
@@ -0,0 +1,116 @@
+//! The strings module pulls the string literals out of
+//! source, for tools such as translation extractors
+
+use super::lexer::{lex, LexError, TokenType};
+use super::span::Span;
+
+/// Every string literal in the source with its span, in source order.
+///
+/// Plain strings give their decoded value. An f-string gives its text with
+/// each replacement field written as `{}`, in the style of `str.format`, so
+/// `f"Hi {name}!"` gives `Hi {}!`. Doubled braces stay doubled, and escapes in an
+/// f-string are left as written, since the lexer keeps f-string text raw.
+/// Bytes literals are not text and are skipped. Adjacent literals are not
+/// joined, each has its own span.
+///
+/// ```
+/// extern crate oxy_python;
+/// use oxy_python::parser::string_literals;
+/// let literals = string_literals("print('a', f'b {c}')").unwrap();
+/// assert_eq!(literals[0].1, "a");
+/// assert_eq!(literals[1].1, "b {}");
+/// ```
+pub fn string_literals(source: &str) -> Result<Vec<(Span, String)>, LexError> {
+    let tokens = lex(source)?;
+    let literals = tokens
+        .iter()
+        .filter_map(|token| match token.token_type() {
+            TokenType::Str(value) => Some((token.span(), value.clone())),
+            TokenType::FString(text) => Some((token.span(), fstring_template(text))),
+            _ => None,
+        })
+        .collect();
+    Ok(literals)
+}
+
+/// The text of an f-string with each replacement field replaced by `{}`
+fn fstring_template(text: &str) -> String {
+    let mut template = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                template.push_str("{{");
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                template.push_str("}}");
+            }
+            '{' => {
+                skip_field(&mut chars);
+                template.push_str("{}");
+            }
+            _ => template.push(c),
+        }
+    }
+    template
+}
+
+/// Skip past the `}` that closes a replacement field, allowing for
+/// brackets and strings inside the expression and its format spec
+fn skip_field<I: Iterator<Item = char>>(chars: &mut I) {
+    let mut depth = 0;
+    let mut quote: Option<char> = None;
+    for c in chars {
+        match (quote, c) {
+            (Some(open), _) if c == open => quote = None,
+            (Some(_), _) => {}
+            (None, '\'') | (None, '"') => quote = Some(c),
+            (None, '{') | (None, '(') | (None, '[') => depth += 1,
+            (None, '}') if depth == 0 => return,
+            (None, '}') | (None, ')') | (None, ']') => depth -= 1,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The start and end of a span as line and column pairs
+    fn bounds(span: &Span) -> ((u64, u64), (u64, u64)) {
+        (
+            (span.start().line(), span.start().column()),
+            (span.end().line(), span.end().column()),
+        )
+    }
+
+    #[test]
+    fn test_string_literals() {
+        let source = "title = _('Hello')\nhelp = \"\"\"Line one\nline two\"\"\"\ndata = b'raw'\nmsg = 'a\\tb' 'c'\n";
+        let literals = string_literals(source).unwrap();
+        let values: Vec<&str> = literals.iter().map(|(_, value)| value.as_str()).collect();
+        assert_eq!(values, ["Hello", "Line one\nline two", "a\tb", "c"]);
+        assert_eq!(bounds(&literals[0].0), ((1, 11), (1, 17)));
+        assert_eq!(bounds(&literals[1].0), ((2, 8), (3, 11)));
+        assert_eq!(bounds(&literals[2].0), ((5, 7), (5, 12)));
+        assert_eq!(bounds(&literals[3].0), ((5, 14), (5, 16)));
+    }
+
+    #[test]
+    fn test_fstring_templates() {
+        let values = |source| -> Vec<String> {
+            string_literals(source)
+                .unwrap()
+                .into_iter()
+                .map(|(_, value)| value)
+                .collect()
+        };
+        assert_eq!(values("f'Hi {name}!'"), ["Hi {}!"]);
+        assert_eq!(values("f'{a:{width}} and {{b}}'"), ["{} and {{b}}"]);
+        assert_eq!(values("f\"{d['}']} {f(x)[0]}\""), ["{} {}"]);
+        assert_eq!(values("f'\\n{x}'"), ["\\n{}"]);
+    }
+}
@@ -0,0 +1,229 @@
+//! The tokenize module classifies tokens the way
+//! CPython's `tokenize` module does, for cross-checking
+
+use std::collections::BTreeSet;
+
+use super::lexer::{lex_with_options, LexError, LexerOptions, TokenCategory, TokenType};
+use super::lines::lines_iter;
+
+/// The token types of CPython's `tokenize` module
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PythonTokenKind {
+    /// The encoding of the source, always first
+    Encoding,
+    /// Names and keywords
+    Name,
+    /// Number literals
+    Number,
+    /// String, bytes and f-string literals
+    String,
+    /// Operators, brackets and delimiters
+    Op,
+    /// The end of a logical line
+    Newline,
+    /// A line break that does not end a logical line
+    Nl,
+    /// The code has been indented one level
+    Indent,
+    /// The code has been dedented one level
+    Dedent,
+    /// A comment
+    Comment,
+    /// The end of the source, always last
+    EndMarker,
+}
+
+impl PythonTokenKind {
+    /// The name CPython gives this type in `tokenize.tok_name`
+    pub fn name(&self) -> &'static str {
+        match self {
+            PythonTokenKind::Encoding => "ENCODING",
+            PythonTokenKind::Name => "NAME",
+            PythonTokenKind::Number => "NUMBER",
+            PythonTokenKind::String => "STRING",
+            PythonTokenKind::Op => "OP",
+            PythonTokenKind::Newline => "NEWLINE",
+            PythonTokenKind::Nl => "NL",
+            PythonTokenKind::Indent => "INDENT",
+            PythonTokenKind::Dedent => "DEDENT",
+            PythonTokenKind::Comment => "COMMENT",
+            PythonTokenKind::EndMarker => "ENDMARKER",
+        }
+    }
+}
+
+/// Lex the source and classify each token as CPython's `tokenize` would.
+///
+/// The result matches the types from `tokenize.tokenize`, including the
+/// leading `ENCODING`, `COMMENT` tokens, `NL` for line breaks that do not end
+/// a logical line, the `NEWLINE` added when the source does not end in one,
+/// and the final `ENDMARKER`. Keywords are `NAME`s, and f-strings are single
+/// `STRING`s as they were before Python 3.12.
+///
+/// ```
+/// extern crate oxy_python;
+/// use oxy_python::parser::{to_python_repr_tokens, PythonTokenKind};
+/// let kinds = to_python_repr_tokens("a\n").unwrap();
+/// let names: Vec<&str> = kinds.iter().map(PythonTokenKind::name).collect();
+/// assert_eq!(names, vec!["ENCODING", "NAME", "NEWLINE", "ENDMARKER"]);
+/// ```
+pub fn to_python_repr_tokens(source: &str) -> Result<Vec<PythonTokenKind>, LexError> {
+    let options = LexerOptions {
+        emit_nl_tokens: true,
+        ..LexerOptions::default()
+    };
+    let tokens = lex_with_options(source, &options)?;
+    let mut comments: BTreeSet<u64> = lines_iter(source, &tokens)
+        .filter(|line| line.comment.is_some())
+        .map(|line| line.number)
+        .collect();
+
+    let mut kinds = vec![PythonTokenKind::Encoding];
+    for token in &tokens {
+        let kind = match token.token_type() {
+            TokenType::Newline | TokenType::Nl => {
+                // A comment comes just before the line break that ends its line
+                if comments.remove(&token.start().line()) {
+                    kinds.push(PythonTokenKind::Comment);
+                }
+                if token.is_type(&TokenType::Newline) {
+                    PythonTokenKind::Newline
+                } else {
+                    PythonTokenKind::Nl
+                }
+            }
+            TokenType::Indent => PythonTokenKind::Indent,
+            TokenType::Dedent => PythonTokenKind::Dedent,
+            token_type => match token_type.category() {
+                TokenCategory::Name | TokenCategory::Keyword => PythonTokenKind::Name,
+                TokenCategory::Literal => match token_type {
+                    TokenType::Int(_) | TokenType::Float(_) | TokenType::Imaginary(_) => {
+                        PythonTokenKind::Number
+                    }
+                    _ => PythonTokenKind::String,
+                },
+                _ => PythonTokenKind::Op,
+            },
+        };
+        kinds.push(kind);
+    }
+
+    // The last line has no line break, CPython still ends it before the closing dedents
+    let dedents = kinds
+        .iter()
+        .rev()
+        .take_while(|kind| **kind == PythonTokenKind::Dedent)
+        .count();
+    let end = kinds.len() - dedents;
+    let mut last_line = Vec::new();
+    let has_comment = !comments.is_empty();
+    if has_comment {
+        last_line.push(PythonTokenKind::Comment);
+    }
+    match kinds[end - 1] {
+        PythonTokenKind::Encoding | PythonTokenKind::Newline | PythonTokenKind::Nl => {
+            if has_comment {
+                last_line.push(PythonTokenKind::Nl);
+            }
+        }
+        _ => last_line.push(PythonTokenKind::Newline),
+    }
+    kinds.splice(end..end, last_line);
+    kinds.push(PythonTokenKind::EndMarker);
+
+    Ok(kinds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(source: &str) -> Vec<&'static str> {
+        to_python_repr_tokens(source)
+            .unwrap()
+            .iter()
+            .map(PythonTokenKind::name)
+            .collect()
+    }
+
+    #[test]
+    fn test_matches_cpython() {
+        // From tokenize.tokenize in CPython 3.11
+        let source =
+            "import os\n\n\ndef f(x):  # doc\n    # note\n\n    return (x +\n            1)\nf(2)";
+        let expected = vec![
+            "ENCODING",
+            "NAME",
+            "NAME",
+            "NEWLINE",
+            "NL",
+            "NL",
+            "NAME",
+            "NAME",
+            "OP",
+            "NAME",
+            "OP",
+            "OP",
+            "COMMENT",
+            "NEWLINE",
+            "COMMENT",
+            "NL",
+            "NL",
+            "INDENT",
+            "NAME",
+            "OP",
+            "NAME",
+            "OP",
+            "NL",
+            "NUMBER",
+            "OP",
+            "NEWLINE",
+            "DEDENT",
+            "NAME",
+            "OP",
+            "NUMBER",
+            "OP",
+            "NEWLINE",
+            "ENDMARKER",
+        ];
+        assert_eq!(names(source), expected);
+    }
+
+    #[test]
+    fn test_literals_and_end_of_file() {
+        assert_eq!(names(""), vec!["ENCODING", "ENDMARKER"]);
+        assert_eq!(
+            names("x = b'a' + 1.5j  # c"),
+            vec![
+                "ENCODING",
+                "NAME",
+                "OP",
+                "STRING",
+                "OP",
+                "NUMBER",
+                "COMMENT",
+                "NEWLINE",
+                "ENDMARKER"
+            ]
+        );
+        assert_eq!(
+            names("a\n# c"),
+            vec!["ENCODING", "NAME", "NEWLINE", "COMMENT", "NL", "ENDMARKER"]
+        );
+        assert_eq!(
+            names("if a:\n    b"),
+            vec![
+                "ENCODING",
+                "NAME",
+                "NAME",
+                "OP",
+                "NEWLINE",
+                "INDENT",
+                "NAME",
+                "NEWLINE",
+                "DEDENT",
+                "ENDMARKER"
+            ]
+        );
+    }
+}
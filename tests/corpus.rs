@@ -0,0 +1,71 @@
+//! Lexes every snippet in `tests/corpus` and checks the token stream is balanced.
+//!
+//! This is broad coverage of real Python rather than exact token assertions.
+
+use std::fs;
+use std::path::Path;
+
+use oxy_python::parser::{lex, Token, TokenType};
+
+/// Check that blocks and brackets open and close in pairs
+fn assert_balanced(name: &str, tokens: &[Token]) {
+    let mut indents = 0i64;
+    // Open parentheses, brackets and braces
+    let mut depths = [0i64; 3];
+
+    for token in tokens {
+        match token.token_type() {
+            TokenType::Indent => indents += 1,
+            TokenType::Dedent => indents -= 1,
+            TokenType::LeftParen => depths[0] += 1,
+            TokenType::RightParen => depths[0] -= 1,
+            TokenType::LeftBracket => depths[1] += 1,
+            TokenType::RightBracket => depths[1] -= 1,
+            TokenType::LeftBrace => depths[2] += 1,
+            TokenType::RightBrace => depths[2] -= 1,
+            _ => {}
+        }
+        assert!(
+            indents >= 0,
+            "{}: dedent before indent at {:?}",
+            name,
+            token.start()
+        );
+        assert!(
+            depths.iter().all(|&depth| depth >= 0),
+            "{}: bracket closed before it was opened at {:?}",
+            name,
+            token.start()
+        );
+    }
+
+    assert_eq!(indents, 0, "{}: unbalanced indent and dedent", name);
+    assert_eq!(depths, [0, 0, 0], "{}: unbalanced brackets", name);
+}
+
+#[test]
+fn test_lex_corpus() {
+    let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/corpus");
+    let mut count = 0;
+
+    for entry in fs::read_dir(corpus).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension() != Some("py".as_ref()) {
+            continue;
+        }
+
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        let source = fs::read_to_string(&path).unwrap();
+        match lex(&source) {
+            Ok(tokens) => assert_balanced(&name, &tokens),
+            Err(e) => panic!("{}: failed to lex: {:?}", name, e),
+        }
+        count += 1;
+    }
+
+    assert!(
+        count >= 10,
+        "Expected at least 10 snippets, found {}",
+        count
+    );
+}
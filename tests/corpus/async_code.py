@@ -0,0 +1,17 @@
+import asyncio
+
+
+async def fetch(delay):
+    await asyncio.sleep(delay)
+    return delay
+
+
+async def main():
+    results = await asyncio.gather(fetch(1), fetch(2))
+    async with asyncio.timeout(10):
+        async for item in stream():
+            print(item)
+    return [r async for r in aiter(results)]
+
+
+asyncio.run(main())
@@ -0,0 +1,23 @@
+class Shape:
+    sides = 0
+
+    def __init__(self, name):
+        self.name = name
+
+    def area(self):
+        raise NotImplementedError
+
+
+class Rectangle(Shape):
+    sides = 4
+
+    def __init__(self, width, height):
+        super().__init__("rectangle")
+        self.width = width
+        self.height = height
+
+    def area(self):
+        return self.width * self.height
+
+    def __repr__(self):
+        return f"Rectangle({self.width!r}, {self.height!r})"
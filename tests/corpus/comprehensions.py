@@ -0,0 +1,9 @@
+squares = [x * x for x in range(10)]
+evens = {x for x in squares if x % 2 == 0}
+lookup = {name: len(name) for name in ["a", "bb", "ccc"]}
+pairs = [(a, b) for a in range(3) for b in range(3) if a != b]
+total = sum(x for x in range(100) if not x % 3)
+matrix = [
+    [row * col for col in range(4)]
+    for row in range(4)
+]
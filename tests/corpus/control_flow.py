@@ -0,0 +1,20 @@
+def classify(n):
+    if n < 0:
+        return "negative"
+    elif n == 0:
+        return "zero"
+    else:
+        pass
+
+    while n > 100:
+        n //= 2
+        if n % 7 == 0:
+            break
+    else:
+        n = -n
+
+    for i in range(n):
+        if i in (1, 2, 3):
+            continue
+        del i
+    return "positive" if n else "reduced"
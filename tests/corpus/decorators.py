@@ -0,0 +1,25 @@
+import functools
+
+
+def logged(func):
+    @functools.wraps(func)
+    def wrapper(*args, **kwargs):
+        print("calling", func.__name__)
+        return func(*args, **kwargs)
+
+    return wrapper
+
+
+class Config:
+    @property
+    def value(self):
+        return self._value
+
+    @value.setter
+    def value(self, new):
+        self._value = new
+
+    @staticmethod
+    @logged
+    def create():
+        return Config()
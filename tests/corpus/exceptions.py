@@ -0,0 +1,16 @@
+class ParseFailure(Exception):
+    pass
+
+
+def parse(text):
+    try:
+        value = int(text)
+    except (TypeError, ValueError) as error:
+        raise ParseFailure(text) from error
+    except Exception:
+        raise
+    else:
+        assert value is not None, "parsed to nothing"
+        return value
+    finally:
+        print("done")
@@ -0,0 +1,10 @@
+name = "world"
+width = 10
+print(f"Hello, {name}!")
+print(f'{name!r:>{width}}')
+print(f"{'nested'} quotes")
+print(F"{3.14159:.2f}")
+print(rf"raw {name} \d+")
+message = f"""multi
+line {name}
+"""
@@ -0,0 +1,26 @@
+def greet(name: str, *, punctuation: str = "!") -> str:
+    """Return a greeting for name."""
+    return "Hello, " + name + punctuation
+
+
+def total(*args, **kwargs):
+    result = 0
+    for value in args:
+        result += value
+    for key in kwargs:
+        result += kwargs[key]
+    return result
+
+
+def outer():
+    count = 0
+
+    def inner():
+        nonlocal count
+        count += 1
+        return count
+
+    return inner
+
+
+square = lambda x: x ** 2
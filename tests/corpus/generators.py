@@ -0,0 +1,18 @@
+def countdown(n):
+    while n:
+        yield n
+        n -= 1
+
+
+def chain(*iterables):
+    for iterable in iterables:
+        yield from iterable
+
+
+def accumulate():
+    total = 0
+    while True:
+        value = yield total
+        if value is None:
+            return total
+        total += value
@@ -0,0 +1,19 @@
+import os
+import os.path as osp
+from collections import (
+    OrderedDict,
+    defaultdict,
+)
+from . import sibling
+from ..parent import *
+
+counter = 0
+
+
+def bump():
+    global counter
+    counter += 1
+
+
+with open(osp.join("a", "b")) as f, open("c") as g:
+    contents = f.read() + g.read()
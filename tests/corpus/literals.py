@@ -0,0 +1,13 @@
+integers = [0, 7, 1_000_000, 0xff, 0o17, 0b1010]
+floats = [3.14, .5, 1., 1e10, 2.5E-3, 1_0.0_1]
+complex_numbers = [1j, 2.5J, 1e3j]
+strings = ['single', "double", '''triple
+single''', """triple
+double"""]
+escapes = "tab\tnewline\nquote\"unicode\u00e9hex\x41"
+raw = r"C:\path\to\file"
+data = b"\x00\x01bytes"
+raw_bytes = br"\d+"
+nothing = None
+flags = (True, False)
+rest = ...
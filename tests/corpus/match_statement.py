@@ -0,0 +1,12 @@
+def describe(command):
+    match command.split():
+        case [action]:
+            return action
+        case ["go", direction] if direction in {"north", "south"}:
+            return direction
+        case {"x": x, "y": y, **rest}:
+            return x, y, rest
+        case Point(x=0, y=0) | None:
+            return "origin"
+        case _:
+            return "unknown"
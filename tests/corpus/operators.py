@@ -0,0 +1,12 @@
+a = 1 + 2 - 3 * 4 / 5 // 6 % 7 ** 8
+b = ~a & a | a ^ a << 2 >> 1
+c = a == b != a < b > a <= b >= a
+matrix @= other @ matrix
+a += 1; a -= 1; a *= 2; a /= 2
+a //= 1; a %= 3; a **= 2; a &= 1
+a |= 2; a ^= 3; a <<= 1; a >>= 1
+if (n := len(items)) > 10:
+    print(n, not a, a and b or c, a is not b)
+result = items[1:-1:2]
+total = a + \
+    b
@@ -0,0 +1,13 @@
+# A comment before any code
+
+def tabbed():
+	value = 1
+
+	# Comments do not affect indentation
+	if value:
+		return value
+	return 0
+
+
+
+class Empty: pass
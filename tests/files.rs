@@ -0,0 +1,39 @@
+//! Reads real files from disk with `lex_files`.
+
+mod common;
+
+use oxy_python::parser::{lex_files, LexError, TokenType};
+
+use common::TempDir;
+
+#[test]
+fn test_lex_files_keeps_paths() {
+    let dir = TempDir::new("lex-files");
+    let first = dir.write("first.py", "a = 1\n");
+    let second = dir.write("second.py", "if b:\n    c\n");
+
+    let files = lex_files(&[first.clone(), second.clone()]).unwrap();
+    assert_eq!(files.len(), 2);
+    assert_eq!(files[0].0, first);
+    assert_eq!(files[1].0, second);
+    assert_eq!(files[0].1[0].token_type(), &TokenType::Name("a".to_owned()));
+    assert_eq!(files[1].1[0].token_type(), &TokenType::If);
+    // Each file is lexed on its own, so the second starts at line 1
+    assert_eq!(files[1].1[0].start().line(), 1);
+}
+
+#[test]
+fn test_lex_files_names_the_bad_file() {
+    let dir = TempDir::new("lex-files-error");
+    let good = dir.write("good.py", "a\n");
+    let bad = dir.write("bad.py", "a $\n");
+    let missing = dir.0.join("missing.py");
+
+    let error = lex_files(&[good.clone(), bad.clone(), missing.clone()]).unwrap_err();
+    assert_eq!(error.file, bad.display().to_string());
+    assert!(matches!(error.error, LexError::UnexpectedToken('$', _, _)));
+
+    let error = lex_files(&[good, missing.clone()]).unwrap_err();
+    assert_eq!(error.file, missing.display().to_string());
+    assert!(matches!(error.error, LexError::Io(_)));
+}
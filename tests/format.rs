@@ -0,0 +1,60 @@
+//! Formats the messy fixture in `tests/format`, through the library and the `fmt` command.
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::process::{self, Command};
+
+use oxy_python::parser::format_source;
+
+use common::TempDir;
+
+fn fixture(name: &str) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/format")
+        .join(name);
+    fs::read_to_string(path).unwrap()
+}
+
+/// Run `oxy-py fmt` with the arguments
+fn fmt(args: &[&str]) -> process::Output {
+    Command::new(env!("CARGO_BIN_EXE_oxy-py"))
+        .arg("fmt")
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_format_fixture() {
+    let expected = fixture("messy_formatted.py");
+    assert_eq!(format_source(&fixture("messy.py")).unwrap(), expected);
+    assert_eq!(format_source(&expected).unwrap(), expected);
+}
+
+#[test]
+fn test_fmt_command() {
+    let dir = TempDir::new("fmt");
+    let path = dir.write("messy.py", &fixture("messy.py"));
+    let file = path.to_str().unwrap();
+    let expected = fixture("messy_formatted.py");
+
+    let check = fmt(&["--check", file]);
+    assert_eq!(check.status.code(), Some(1));
+
+    let stdout = fmt(&["--stdout", file]);
+    assert!(stdout.status.success());
+    assert_eq!(String::from_utf8(stdout.stdout).unwrap(), expected);
+
+    // Writing back formats the file, and doing it again changes nothing
+    assert!(fmt(&[file]).status.success());
+    assert_eq!(fs::read_to_string(&path).unwrap(), expected);
+    assert!(fmt(&["--check", file]).status.success());
+    assert!(fmt(&[file]).status.success());
+    assert_eq!(fs::read_to_string(&path).unwrap(), expected);
+
+    let broken = dir.write("broken.py", "a $\n");
+    assert_eq!(fmt(&[broken.to_str().unwrap()]).status.code(), Some(2));
+    assert_eq!(fmt(&[]).status.code(), Some(2));
+}
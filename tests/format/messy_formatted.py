@@ -0,0 +1,18 @@
+import os, sys
+from ...pkg import y
+from ... import z
+def f(a, b=1, *args, **kwargs):
+    x = a + b * -1  # comment
+    if x > 0:
+        return x[1:2], {"k": x}
+    values = [
+      1, 2,
+      3]
+    g(a=(1), key=[2])
+    return f(a, b=2); y = lambda n: n ** 2
+
+
+@decorator
+class A:
+    def g(self, a: int = 1) -> int:
+        return self.x  # trailing
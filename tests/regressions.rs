@@ -0,0 +1,64 @@
+//! Lexes every input in `tests/regressions` and compares the result with its snapshot.
+//!
+//! Each `name.py` has a `name.snap` next to it holding one line per token, or the
+//! error. Run with `UPDATE_SNAPSHOTS=1` to write the snapshots after a deliberate change.
+
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use oxy_python::parser::lex;
+
+/// Render the result of lexing the source in a stable, line based form
+fn render(source: &str) -> String {
+    let mut rendered = String::new();
+    match lex(source) {
+        Ok(tokens) => {
+            for token in tokens {
+                let (start, end) = (token.start(), token.end());
+                writeln!(
+                    rendered,
+                    "{}:{}-{}:{} {:?}",
+                    start.line(),
+                    start.column(),
+                    end.line(),
+                    end.column(),
+                    token.token_type()
+                )
+                .unwrap();
+            }
+        }
+        Err(e) => writeln!(rendered, "error: {}", e).unwrap(),
+    }
+    rendered
+}
+
+#[test]
+fn test_regressions() {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/regressions");
+    let update = env::var_os("UPDATE_SNAPSHOTS").is_some();
+    let mut count = 0;
+
+    for entry in fs::read_dir(directory).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension() != Some("py".as_ref()) {
+            continue;
+        }
+
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        let source = fs::read_to_string(&path).unwrap();
+        let rendered = render(&source);
+        let snapshot = path.with_extension("snap");
+        if update {
+            fs::write(&snapshot, &rendered).unwrap();
+        } else {
+            let expected = fs::read_to_string(&snapshot)
+                .unwrap_or_else(|e| panic!("{}: could not read the snapshot: {}", name, e));
+            assert_eq!(rendered, expected, "{}: the result changed", name);
+        }
+        count += 1;
+    }
+
+    assert!(count >= 4, "Expected at least 4 inputs, found {}", count);
+}
@@ -0,0 +1,47 @@
+//! Runs the `tokens` command on a small file with each `--color` mode.
+
+mod common;
+
+use std::process::{self, Command};
+
+use common::TempDir;
+
+/// Run `oxy-py tokens` with the arguments
+fn tokens(args: &[&str]) -> process::Output {
+    Command::new(env!("CARGO_BIN_EXE_oxy-py"))
+        .arg("tokens")
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_tokens_command() {
+    let dir = TempDir::new("tokens");
+    let path = dir.write("tokens.py", "if a:\n    b\n");
+    let file = path.to_str().unwrap();
+
+    let plain = tokens(&[file]);
+    assert!(plain.status.success());
+    let plain = String::from_utf8(plain.stdout).unwrap();
+    assert!(plain.starts_with("1:1-1:2 If\n"), "{}", plain);
+    // The output of a test is not a terminal, so auto is the same as never
+    assert_eq!(
+        String::from_utf8(tokens(&["--color", "never", file]).stdout).unwrap(),
+        plain
+    );
+
+    let colored = String::from_utf8(tokens(&["--color", "always", file]).stdout).unwrap();
+    assert!(
+        colored.starts_with("1:1-1:2 \x1b[35mIf\x1b[0m\n"),
+        "{}",
+        colored
+    );
+    assert_eq!(colored.lines().count(), plain.lines().count());
+
+    assert_eq!(
+        tokens(&["--color", "sometimes", file]).status.code(),
+        Some(2)
+    );
+    assert_eq!(tokens(&[]).status.code(), Some(2));
+}